@@ -0,0 +1,180 @@
+/*
+ * The HD44780 character ROM (the A00 variant is what ships on the cheap
+ * 2004 boards) is mostly ASCII, plus katakana and a handful of Latin and
+ * Greek characters. The proxy keeps sign text inside that set with
+ * lcd_safe, so the firmware never has to guess at what a multi-byte
+ * character should be, and the sign maps it onto ROM codes with encode.
+ */
+use alloc::{string::String, vec::Vec};
+
+// Custom characters the firmware loads into CGRAM at boot
+pub const GLYPH_FREE: char = '○';
+pub const GLYPH_BUSY: char = '●';
+
+// Characters outside of ASCII that the sign can draw, and where they live.
+// The ROM has ¥ and → where ASCII has \ and ~.
+const ROM: &[(char, u8)] = &[
+    // Custom characters live at the bottom of the ROM
+    (GLYPH_FREE, 0x00),
+    (GLYPH_BUSY, 0x01),
+    ('¥', 0x5C),
+    ('→', 0x7E),
+    ('←', 0x7F),
+    ('ä', 0xE1),
+    ('ß', 0xE2),
+    ('µ', 0xE4),
+    ('√', 0xE8),
+    ('¢', 0xEC),
+    ('ñ', 0xEE),
+    ('ö', 0xEF),
+    ('∞', 0xF3),
+    ('Ω', 0xF4),
+    ('ü', 0xF5),
+    ('Σ', 0xF6),
+    ('π', 0xF7),
+    ('÷', 0xFD),
+    ('°', 0xDF),
+    ('█', 0xFF),
+];
+
+// Can this character go to the sign as-is?
+pub fn is_renderable(c: char) -> bool {
+    (' '..='}').contains(&c) && c != '\\' || ROM.iter().any(|(r, _)| *r == c)
+}
+
+// Swap out anything the ROM can't draw for the closest thing it can,
+// or '?' if there isn't one. Newlines are left alone.
+pub fn lcd_safe(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c == '\n' || is_renderable(c) {
+            out.push(c);
+            continue;
+        }
+        match transliterate(c) {
+            Some(t) => out.push_str(t),
+            None if c.is_control() => {}
+            None => out.push('?'),
+        }
+    }
+    out
+}
+
+// The closest thing the ROM can draw, for characters it can't
+pub fn transliterate(c: char) -> Option<&'static str> {
+    let t = match c {
+        '\t' => " ",
+        '\\' => "/",
+        '~' => "-",
+        'à' | 'á' | 'â' | 'ã' | 'å' | 'ą' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Å' | 'Ą' | 'Ä' => "A",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ç' | 'ć' | 'č' => "c",
+        'Ç' | 'Ć' | 'Č' => "C",
+        'è' | 'é' | 'ê' | 'ë' | 'ę' | 'ě' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ę' | 'Ě' => "E",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'ł' => "l",
+        'Ł' => "L",
+        'ń' | 'ň' => "n",
+        'Ń' | 'Ň' | 'Ñ' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ø' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ø' | 'Ö' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ś' | 'š' => "s",
+        'Ś' | 'Š' => "S",
+        'ù' | 'ú' | 'û' | 'ů' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ů' | 'Ü' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ź' | 'ż' | 'ž' => "z",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        '‘' | '’' | '´' | '`' => "'",
+        '“' | '”' | '„' => "\"",
+        '–' | '—' => "-",
+        '…' => "...",
+        '·' | '•' => "-",
+        '\u{a0}' => " ",
+        _ => return None,
+    };
+    Some(t)
+}
+
+// Map text that's been through lcd_safe onto the HD44780 A00 character
+// ROM. Anything else comes out as '?'.
+pub fn encode_char(c: char) -> u8 {
+    match c {
+        ' '..='}' if c != '\\' => c as u8,
+        _ => ROM
+            .iter()
+            .find(|(r, _)| *r == c)
+            .map(|(_, b)| *b)
+            .unwrap_or(b'?'),
+    }
+}
+
+// What a ROM byte looks like, for anything drawing a sign that isn't one.
+// Katakana come out as their half-width forms. Codes the ROM leaves blank,
+// and CGRAM slots we don't load, come out as spaces.
+pub fn decode(b: u8) -> char {
+    match b {
+        0x20..=0x7D if b != 0x5C => b as char,
+        0xA1..=0xDE => char::from_u32(0xFF61 + (b - 0xA1) as u32).unwrap_or(' '),
+        _ => ROM
+            .iter()
+            .find(|(_, r)| *r == b)
+            .map(|(c, _)| *c)
+            .unwrap_or(' '),
+    }
+}
+
+// One byte per character, so a line's length on the display is the
+// length of what this returns.
+pub fn encode(s: &str) -> Vec<u8> {
+    s.chars().map(encode_char).collect()
+}
+
+// Custom characters, loaded into CGRAM at boot in this order: GLYPH_FREE,
+// then GLYPH_BUSY.
+pub const GLYPHS: [[u8; 8]; 2] = [
+    // ○ Free
+    [
+        0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000, 0b00000,
+    ],
+    // ● Busy
+    [
+        0b00000, 0b01110, 0b11111, 0b11111, 0b11111, 0b01110, 0b00000, 0b00000,
+    ],
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::charset::{decode, encode, lcd_safe};
+
+    #[test]
+    fn test_round_trip() {
+        let text = "● Mañana 19:00 ¥→";
+        let decoded: alloc::string::String = encode(text).into_iter().map(decode).collect();
+        assert_eq!(decoded, text);
+
+        assert_eq!(encode("a\\b~"), b"a?b?");
+        assert_eq!(decode(0xB1), 'ｱ');
+        assert_eq!(decode(0x05), ' ');
+    }
+
+    #[test]
+    fn test_lcd_safe() {
+        assert_eq!(lcd_safe("Board Games"), "Board Games");
+        assert_eq!(lcd_safe("Mañana 19:00"), "Mañana 19:00");
+        assert_eq!(lcd_safe("Aucun événement."), "Aucun evenement.");
+        assert_eq!(lcd_safe("Zostało 5m\nŚro"), "Zostalo 5m\nSro");
+        assert_eq!(lcd_safe("Über ~ C:\\"), "Uber - C:/");
+        assert_eq!(lcd_safe("Pizza 🍕"), "Pizza ?");
+        assert_eq!(lcd_safe("● Lounge"), "● Lounge");
+        // Everything that comes out has somewhere to go in the ROM
+        assert!(!encode(&lcd_safe("Mañana — Über")).contains(&b'?'));
+    }
+}
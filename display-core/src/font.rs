@@ -0,0 +1,175 @@
+/*
+ * The HD44780 A00 ROM's 5x8 dot matrix font, for anything drawing a sign
+ * that isn't one. Each glyph is eight rows, top to bottom, with the leftmost
+ * dot in bit 4. The bottom row is where the cursor goes, so it's blank for
+ * everything but a couple of descenders.
+ *
+ * This only covers what charset::encode can produce. The rest of the ROM
+ * (mostly katakana) comes out blank.
+ */
+
+use crate::charset::GLYPHS;
+
+pub const WIDTH: usize = 5;
+pub const HEIGHT: usize = 8;
+
+// 0x20 to 0x7F
+const ASCII: [[u8; 8]; 96] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x00, 0x00, 0x04, 0x00], // !
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A, 0x00], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04, 0x00], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03, 0x00], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D, 0x00], // &
+    [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02, 0x00], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08, 0x00], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08, 0x00], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E, 0x00], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F, 0x00], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E, 0x00], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02, 0x00], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E, 0x00], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E, 0x00], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08, 0x00], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E, 0x00], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C, 0x00], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08, 0x00], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02, 0x00], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08, 0x00], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04, 0x00], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E, 0x00], // @
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x00], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E, 0x00], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E, 0x00], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C, 0x00], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F, 0x00], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10, 0x00], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F, 0x00], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C, 0x00], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11, 0x00], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F, 0x00], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11, 0x00], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x00], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10, 0x00], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D, 0x00], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11, 0x00], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E, 0x00], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A, 0x00], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11, 0x00], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x00], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F, 0x00], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E, 0x00], // [
+    [0x11, 0x0A, 0x1F, 0x04, 0x1F, 0x04, 0x04, 0x00], // ¥
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E, 0x00], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F, 0x00], // _
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F, 0x00], // a
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E, 0x00], // b
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E, 0x00], // c
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F, 0x00], // d
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E, 0x00], // e
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08, 0x00], // f
+    [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E, 0x00], // g
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // h
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E, 0x00], // i
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C, 0x00], // j
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12, 0x00], // k
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // l
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11, 0x00], // m
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // n
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E, 0x00], // o
+    [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10, 0x00], // p
+    [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01, 0x00], // q
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10, 0x00], // r
+    [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E, 0x00], // s
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06, 0x00], // t
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D, 0x00], // u
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // v
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A, 0x00], // w
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x00], // x
+    [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E, 0x00], // y
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F, 0x00], // z
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02, 0x00], // {
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // |
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08, 0x00], // }
+    [0x00, 0x04, 0x02, 0x1F, 0x02, 0x04, 0x00, 0x00], // →
+    [0x00, 0x04, 0x08, 0x1F, 0x08, 0x04, 0x00, 0x00], // ←
+];
+
+// The rest of what charset::encode uses
+const EXTRAS: &[(u8, [u8; 8])] = &[
+    (0xDF, [0x1C, 0x14, 0x1C, 0x00, 0x00, 0x00, 0x00, 0x00]), // °
+    (0xE1, [0x0A, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F, 0x00]), // ä
+    (0xE2, [0x00, 0x00, 0x0E, 0x11, 0x1E, 0x11, 0x1E, 0x10]), // ß
+    (0xE4, [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x1D, 0x10]), // µ
+    (0xE8, [0x00, 0x00, 0x07, 0x04, 0x04, 0x14, 0x08, 0x00]), // √
+    (0xEC, [0x00, 0x04, 0x0E, 0x14, 0x15, 0x0E, 0x04, 0x00]), // ¢
+    (0xEE, [0x0A, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00]), // ñ
+    (0xEF, [0x0A, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E, 0x00]), // ö
+    (0xF3, [0x00, 0x00, 0x00, 0x0A, 0x15, 0x0A, 0x00, 0x00]), // ∞
+    (0xF4, [0x00, 0x0E, 0x11, 0x11, 0x11, 0x0A, 0x1B, 0x00]), // Ω
+    (0xF5, [0x0A, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D, 0x00]), // ü
+    (0xF6, [0x1F, 0x10, 0x08, 0x04, 0x08, 0x10, 0x1F, 0x00]), // Σ
+    (0xF7, [0x00, 0x00, 0x1F, 0x0A, 0x0A, 0x0A, 0x13, 0x00]), // π
+    (0xFD, [0x00, 0x04, 0x00, 0x1F, 0x00, 0x04, 0x00, 0x00]), // ÷
+    (0xFF, [0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F, 0x1F]), // █
+];
+
+// The dots for a ROM byte. The first few codes are CGRAM, so they come
+// from our custom characters.
+pub fn glyph(b: u8) -> [u8; 8] {
+    match b {
+        0x00..=0x07 => GLYPHS.get(b as usize).cloned().unwrap_or([0; 8]),
+        0x20..=0x7F => ASCII[(b - 0x20) as usize],
+        _ => EXTRAS
+            .iter()
+            .find(|(code, _)| *code == b)
+            .map(|(_, g)| *g)
+            .unwrap_or([0; 8]),
+    }
+}
+
+// Is the dot at this column and row of a glyph lit?
+pub fn lit(glyph: &[u8; 8], x: usize, y: usize) -> bool {
+    glyph[y] & (0x10 >> x) != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        charset::{decode, encode},
+        font::{glyph, lit},
+    };
+
+    // Everything the sign can be sent has dots, apart from the space
+    #[test]
+    fn test_everything_encodable_has_a_glyph() {
+        for b in (0x00..=0xFF).filter(|&b| encode(&decode(b).to_string()) == [b]) {
+            assert_eq!(glyph(b) == [0; 8], b == b' ', "{:#04x}", b);
+        }
+    }
+
+    #[test]
+    fn test_lit() {
+        let t = glyph(b'T');
+        assert!((0..5).all(|x| lit(&t, x, 0)));
+        assert!(lit(&t, 2, 6) && !lit(&t, 1, 6));
+    }
+}
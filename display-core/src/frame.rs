@@ -0,0 +1,89 @@
+use alloc::{string::String, vec, vec::Vec};
+
+use crate::charset;
+
+// What's on the panel, one ROM byte per cell
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    rows: usize,
+    cols: usize,
+    cells: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Framebuffer {
+            rows,
+            cols,
+            cells: vec![b' '; rows * cols],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn row(&self, row: usize) -> &[u8] {
+        &self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+
+    // Put text on a row, cut off or padded with spaces to fit. Returns
+    // whether anything changed.
+    pub fn set_row(&mut self, row: usize, text: &[u8]) -> bool {
+        let mut t: Vec<u8> = text.iter().take(self.cols).cloned().collect();
+        t.resize(self.cols, b' ');
+        let cells = &mut self.cells[row * self.cols..(row + 1) * self.cols];
+        if cells == t.as_slice() {
+            return false;
+        }
+        cells.copy_from_slice(&t);
+        true
+    }
+}
+
+// Lay out text from the proxy into exactly `rows` rows of ROM bytes.
+// Anything past the last row is dropped, and missing rows are blank.
+pub fn compose<S: AsRef<str>>(lines: &[S], rows: usize) -> Vec<Vec<u8>> {
+    let mut out: Vec<Vec<u8>> = lines
+        .iter()
+        .take(rows)
+        .map(|l| charset::encode(l.as_ref()))
+        .collect();
+    out.resize(rows, vec![]);
+    out
+}
+
+// Split a proxy response body into lines
+pub fn lines(text: &str) -> Vec<String> {
+    text.split('\n').map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::{compose, Framebuffer};
+
+    #[test]
+    fn test_framebuffer_rows() {
+        let mut f = Framebuffer::new(2, 4);
+        assert_eq!(f.row(1), b"    ");
+        assert!(f.set_row(1, b"abcdef"));
+        assert_eq!(f.row(1), b"abcd");
+        assert!(!f.set_row(1, b"abcd"));
+        assert!(f.set_row(0, b"x"));
+        assert_eq!(f.row(0), b"x   ");
+    }
+
+    #[test]
+    fn test_compose() {
+        let rows = compose(&["● Lounge", "Free", "extra"], 4);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], b"\x01 Lounge");
+        assert_eq!(rows[1], b"Free");
+        assert!(rows[3].is_empty());
+        assert_eq!(compose(&["a", "b", "c"], 2).len(), 2);
+    }
+}
@@ -0,0 +1,45 @@
+/*
+ * Everything a Sidegrade sign does that doesn't depend on the hardware it's
+ * running on: what goes on each row, scrolling the rows that don't fit,
+ * keeping track of what's already on the panel so only the rows that change
+ * get redrawn, and making sense of what the proxy sends.
+ *
+ * The firmware implements CharDisplay for its HD44780s and Clock on top of
+ * FreeRTOS. Anything else that wants to behave like a sign, including the
+ * tests in here, can bring its own.
+ */
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod charset;
+pub mod font;
+pub mod frame;
+pub mod proxy;
+pub mod scroll;
+pub mod sign;
+
+// A character display, addressed a row at a time. Text arrives already
+// encoded for the HD44780 character ROM (see charset::encode), one byte
+// per cell.
+pub trait CharDisplay {
+    type Error;
+
+    fn rows(&self) -> usize;
+    fn cols(&self) -> usize;
+
+    // Replace a whole row. `text` is always exactly cols() bytes.
+    fn write_row(&mut self, row: usize, text: &[u8]) -> Result<(), Self::Error>;
+
+    // Blank the display without losing what's on it, or bring it back
+    fn set_visible(&mut self, visible: bool) -> Result<(), Self::Error>;
+
+    // Switch the backlight, for panels that have one
+    fn set_backlight(&mut self, on: bool) -> Result<(), Self::Error>;
+}
+
+// Milliseconds since some fixed point, and a way to wait
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+    fn sleep_ms(&mut self, ms: u64);
+}
@@ -0,0 +1,189 @@
+// What a sign hears from the proxy, and keeping up with it. The firmware
+// and the simulator each bring their own HTTP client; everything after the
+// request has been made is in here, so they can't drift apart.
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{frame, sign::Power, Clock};
+
+// How many milliseconds until the sign should ask again, for when the
+// screen is going to change on its own (page flips)
+pub const REFRESH_HEADER: &str = "X-Sidegrade-Refresh";
+// What state the room's schedule is in, so the sign can tell anyone
+// watching it
+pub const URGENCY_HEADER: &str = "X-Sidegrade-Urgency";
+// Blink the display
+pub const FLASH_HEADER: &str = "X-Sidegrade-Flash";
+// Whether to keep the backlight and display on
+pub const POWER_HEADER: &str = "X-Sidegrade-Power";
+// The screen is an emergency broadcast, to put up straight away
+pub const EMERGENCY_HEADER: &str = "X-Sidegrade-Emergency";
+
+// How soon a sign will ask again, however soon the proxy wants it to
+pub const MIN_DELAY_MS: u64 = 1000;
+
+// A screen from the proxy, headers and all
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub rows: Vec<String>,
+    // How soon the proxy wants to be asked again, if it's sooner than usual
+    pub refresh_ms: Option<u64>,
+    // What the proxy thinks of the schedule
+    pub urgency: Option<String>,
+    // Whether the display should blink, like when an event is starting
+    pub flash: bool,
+    // Whether to light the display at all, for quiet hours and closed rooms
+    pub power: Power,
+}
+
+impl Response {
+    // `header` looks up one of the response's headers by name
+    pub fn parse<'a>(text: &str, header: impl Fn(&str) -> Option<&'a str>) -> Self {
+        Response {
+            rows: frame::lines(text),
+            refresh_ms: header(REFRESH_HEADER).and_then(|r| r.parse::<u64>().ok()),
+            urgency: header(URGENCY_HEADER).map(String::from),
+            flash: header(FLASH_HEADER) == Some("1"),
+            power: header(POWER_HEADER)
+                .and_then(Power::from_name)
+                .unwrap_or_default(),
+        }
+    }
+
+    // How long to wait before the next query: `max_ms`, unless the proxy
+    // says the screen is about to change, like when an agenda flips pages
+    pub fn delay(&self, max_ms: u64) -> u64 {
+        self.refresh_ms
+            .map_or(max_ms, |r| r.clamp(MIN_DELAY_MS, max_ms.max(MIN_DELAY_MS)))
+    }
+}
+
+// The broadcast's rows from the fast poll, or None when there isn't one
+// going. Any status the proxy wouldn't answer with comes back as an error.
+pub fn broadcast(status: u16, text: &str) -> Result<Option<Vec<String>>, u16> {
+    match status {
+        204 => Ok(None),
+        200 => Ok(Some(frame::lines(text))),
+        status => Err(status),
+    }
+}
+
+// Everything the display loop goes by: the last screen from the proxy, and
+// the broadcast, when there's one going
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Directives {
+    pub rows: Vec<String>,
+    pub flash: bool,
+    pub power: Power,
+    pub emergency: Option<Vec<String>>,
+}
+
+// Wait out `delay_ms` until the next query, asking `check` about emergency
+// broadcasts every `poll_ms` so one shows up within seconds. A check that
+// fails is skipped; the regular query will say if the proxy's gone. Each
+// answer goes to `swap`, which hands back the one before it. Once a
+// broadcast is cleared, stop waiting, so the events come straight back.
+pub fn watch_emergency<C: Clock, E>(
+    clock: &mut C,
+    delay_ms: u64,
+    poll_ms: u64,
+    mut check: impl FnMut() -> Result<Option<Vec<String>>, E>,
+    mut swap: impl FnMut(Option<Vec<String>>) -> Option<Vec<String>>,
+) {
+    let mut waited = 0;
+    while waited < delay_ms {
+        let step = poll_ms.max(1).min(delay_ms - waited);
+        clock.sleep_ms(step);
+        waited += step;
+        if let Ok(alert) = check() {
+            let cleared = alert.is_none();
+            if swap(alert).is_some() && cleared {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec, vec::Vec};
+
+    use crate::{
+        proxy::{broadcast, watch_emergency, Response},
+        sign::Power,
+        Clock,
+    };
+
+    struct FakeClock(u64);
+
+    impl Clock for FakeClock {
+        fn now_ms(&self) -> u64 {
+            self.0
+        }
+
+        fn sleep_ms(&mut self, ms: u64) {
+            self.0 += ms;
+        }
+    }
+
+    #[test]
+    fn test_response() {
+        let headers = [
+            ("X-Sidegrade-Refresh", "400"),
+            ("X-Sidegrade-Urgency", "soon"),
+            ("X-Sidegrade-Flash", "1"),
+            ("X-Sidegrade-Power", "dim"),
+        ];
+        let header = |name: &str| headers.iter().find(|(h, _)| *h == name).map(|(_, v)| *v);
+        let response = Response::parse("Smash\nIn 00:15:00", header);
+        assert_eq!(response.rows, ["Smash", "In 00:15:00"]);
+        assert_eq!(response.urgency.as_deref(), Some("soon"));
+        assert!(response.flash);
+        assert_eq!(response.power, Power::Dim);
+        // Never sooner than a second, never later than usual
+        assert_eq!(response.delay(10000), 1000);
+
+        let quiet = Response::parse("Lounge", |_| None);
+        assert_eq!((quiet.flash, quiet.power), (false, Power::On));
+        assert_eq!(quiet.delay(10000), 10000);
+
+        assert_eq!(broadcast(204, ""), Ok(None));
+        assert_eq!(
+            broadcast(200, "FIRE ALARM\nLeave now"),
+            Ok(Some(vec!["FIRE ALARM".into(), "Leave now".into()]))
+        );
+        assert_eq!(broadcast(500, "oops"), Err(500));
+    }
+
+    #[test]
+    fn test_watch_emergency() {
+        let alarm = || Some(vec![String::from("FIRE ALARM")]);
+
+        // Goes up at the first check that sees it, and the wait goes on
+        let mut clock = FakeClock(0);
+        let mut current = None;
+        let mut answers = vec![Err(()), Ok(alarm()), Ok(alarm())].into_iter();
+        watch_emergency(
+            &mut clock,
+            5000,
+            2000,
+            || answers.next().unwrap_or(Ok(None)),
+            |alert| core::mem::replace(&mut current, alert),
+        );
+        assert_eq!(clock.now_ms(), 5000);
+        assert_eq!(current, alarm());
+
+        // Once it's cleared, back to the events right away
+        let mut answers: Vec<Result<_, ()>> = vec![Ok(alarm()), Ok(None), Ok(None)];
+        answers.reverse();
+        watch_emergency(
+            &mut clock,
+            10000,
+            2000,
+            || answers.pop().unwrap(),
+            |alert| core::mem::replace(&mut current, alert),
+        );
+        assert_eq!(clock.now_ms(), 9000);
+        assert_eq!(current, None);
+    }
+}
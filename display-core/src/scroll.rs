@@ -0,0 +1,300 @@
+/*
+ * The scroll engine. Each row of the display gets its own Marquee, which
+ * pans long lines across the row a character at a time, pausing at either
+ * end so that they can actually be read. Rows that fit are left alone.
+ *
+ * Nothing in here touches the hardware or the clock: time comes in as
+ * milliseconds, and text comes in already encoded for the character ROM.
+ * That keeps it testable on the host.
+ */
+
+use alloc::{vec, vec::Vec};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScrollConfig {
+    // How long each one-character step is shown for
+    pub step_ms: u64,
+    // How long to hold the start and end of a long line
+    pub dwell_ms: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    DwellStart,
+    Scrolling,
+    DwellEnd,
+}
+
+pub struct Marquee {
+    text: Vec<u8>,
+    // New text for a scrolling row waits here until the current pass is done
+    pending: Option<Vec<u8>>,
+    width: usize,
+    offset: usize,
+    phase: Phase,
+    // When the current step or dwell started
+    since: u64,
+}
+
+impl Marquee {
+    pub fn new(width: usize) -> Self {
+        Marquee {
+            text: vec![],
+            pending: None,
+            width,
+            offset: 0,
+            phase: Phase::DwellStart,
+            since: 0,
+        }
+    }
+
+    fn scrolls(&self) -> bool {
+        self.text.len() > self.width
+    }
+
+    // Queue up new text. Rows that don't scroll, or haven't started
+    // scrolling yet, change straight away. Rows in the middle of a pass
+    // finish it first, so nobody gets a title cut off halfway through.
+    // Returns whether what's on the row changed.
+    pub fn set_text(&mut self, text: Vec<u8>, now: u64) -> bool {
+        if text == self.text {
+            self.pending = None;
+            return false;
+        }
+        if !self.scrolls() || self.phase == Phase::DwellStart {
+            self.restart(text, now);
+            return true;
+        }
+        self.pending = Some(text);
+        false
+    }
+
+    // Put new text up right now, even in the middle of a pass. For things
+    // that can't wait, like emergencies. Returns whether what's on the row
+    // changed.
+    pub fn replace(&mut self, text: Vec<u8>, now: u64) -> bool {
+        if text == self.text {
+            self.pending = None;
+            return false;
+        }
+        self.restart(text, now);
+        true
+    }
+
+    fn restart(&mut self, text: Vec<u8>, now: u64) {
+        self.text = text;
+        self.pending = None;
+        self.offset = 0;
+        self.phase = Phase::DwellStart;
+        self.since = now;
+    }
+
+    // Move things along. Returns whether what's on the row changed.
+    pub fn tick(&mut self, now: u64, config: &ScrollConfig) -> bool {
+        if !self.scrolls() {
+            return false;
+        }
+        let Some(deadline) = self.deadline(config) else {
+            return false;
+        };
+        if now < deadline {
+            return false;
+        }
+
+        match self.phase {
+            Phase::DwellStart => {
+                self.phase = Phase::Scrolling;
+                self.offset = 1;
+            }
+            Phase::Scrolling => {
+                self.offset += 1;
+            }
+            Phase::DwellEnd => {
+                let text = self
+                    .pending
+                    .take()
+                    .unwrap_or_else(|| core::mem::take(&mut self.text));
+                self.restart(text, now);
+                return true;
+            }
+        }
+        if self.offset >= self.text.len() - self.width {
+            self.offset = self.text.len() - self.width;
+            self.phase = Phase::DwellEnd;
+        }
+        self.since = now;
+        true
+    }
+
+    // When this row next needs a tick, if it's moving at all
+    pub fn deadline(&self, config: &ScrollConfig) -> Option<u64> {
+        if !self.scrolls() {
+            return None;
+        }
+        Some(match self.phase {
+            Phase::DwellStart | Phase::DwellEnd => self.since + config.dwell_ms,
+            Phase::Scrolling => self.since + config.step_ms,
+        })
+    }
+
+    // What should be on the row right now, padded out to its width
+    pub fn window(&self) -> Vec<u8> {
+        let mut t: Vec<u8> = self
+            .text
+            .iter()
+            .skip(self.offset)
+            .take(self.width)
+            .cloned()
+            .collect();
+        t.resize(self.width, b' ');
+        t
+    }
+}
+
+// A Marquee for every row on the display
+pub struct Scroller {
+    pub rows: Vec<Marquee>,
+    pub config: ScrollConfig,
+}
+
+impl Scroller {
+    pub fn new(rows: usize, cols: usize, config: ScrollConfig) -> Self {
+        Scroller {
+            rows: (0..rows).map(|_| Marquee::new(cols)).collect(),
+            config,
+        }
+    }
+
+    // Hand each row its new text. Returns the rows that need redrawing.
+    pub fn set_text(&mut self, text: &[Vec<u8>], now: u64) -> Vec<usize> {
+        let mut changed = vec![];
+        for (idx, row) in self.rows.iter_mut().enumerate() {
+            let t = text.get(idx).cloned().unwrap_or_default();
+            if row.set_text(t, now) {
+                changed.push(idx);
+            }
+        }
+        changed
+    }
+
+    // The same, but without waiting for any passes to finish
+    pub fn replace(&mut self, text: &[Vec<u8>], now: u64) -> Vec<usize> {
+        let mut changed = vec![];
+        for (idx, row) in self.rows.iter_mut().enumerate() {
+            let t = text.get(idx).cloned().unwrap_or_default();
+            if row.replace(t, now) {
+                changed.push(idx);
+            }
+        }
+        changed
+    }
+
+    // Returns the rows that need redrawing
+    pub fn tick(&mut self, now: u64) -> Vec<usize> {
+        let config = self.config;
+        self.rows
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(idx, row)| row.tick(now, &config).then_some(idx))
+            .collect()
+    }
+
+    // When the next row needs a tick, if any of them are moving
+    pub fn deadline(&self) -> Option<u64> {
+        self.rows
+            .iter()
+            .filter_map(|r| r.deadline(&self.config))
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::scroll::{Marquee, ScrollConfig, Scroller};
+
+    const CONFIG: ScrollConfig = ScrollConfig {
+        step_ms: 100,
+        dwell_ms: 1000,
+    };
+
+    #[test]
+    fn test_short_rows_stay_put() {
+        let mut m = Marquee::new(8);
+        assert!(m.set_text(b"Hello".to_vec(), 0));
+        assert_eq!(m.window(), b"Hello   ");
+        assert_eq!(m.deadline(&CONFIG), None);
+        assert!(!m.tick(10_000, &CONFIG));
+
+        // and change as soon as there's something new
+        assert!(m.set_text(b"Bye".to_vec(), 50));
+        assert_eq!(m.window(), b"Bye     ");
+        assert!(!m.set_text(b"Bye".to_vec(), 60));
+    }
+
+    #[test]
+    fn test_long_rows_dwell_then_scroll() {
+        let mut m = Marquee::new(4);
+        m.set_text(b"abcdef".to_vec(), 0);
+        assert_eq!(m.window(), b"abcd");
+
+        // Held at the start
+        assert!(!m.tick(999, &CONFIG));
+        assert!(m.tick(1000, &CONFIG));
+        assert_eq!(m.window(), b"bcde");
+
+        // One character per step, and then held at the end
+        assert!(!m.tick(1099, &CONFIG));
+        assert!(m.tick(1100, &CONFIG));
+        assert_eq!(m.window(), b"cdef");
+        assert!(!m.tick(2099, &CONFIG));
+
+        // Then back to the start
+        assert!(m.tick(2100, &CONFIG));
+        assert_eq!(m.window(), b"abcd");
+        assert_eq!(m.deadline(&CONFIG), Some(3100));
+    }
+
+    #[test]
+    fn test_new_text_waits_for_the_pass_to_finish() {
+        let mut m = Marquee::new(4);
+        m.set_text(b"abcdef".to_vec(), 0);
+
+        // Not moving yet, so it can change right away
+        assert!(m.set_text(b"uvwxyz".to_vec(), 10));
+        assert_eq!(m.window(), b"uvwx");
+
+        m.tick(1010, &CONFIG);
+        assert!(!m.set_text(b"Short".to_vec(), 1020));
+        assert_eq!(m.window(), b"vwxy");
+
+        m.tick(1110, &CONFIG);
+        assert_eq!(m.window(), b"wxyz");
+        assert!(m.tick(2110, &CONFIG));
+        assert_eq!(m.window(), b"Shor");
+
+        // Unless it can't wait
+        m.set_text(b"abcdef".to_vec(), 2200);
+        m.tick(3200, &CONFIG);
+        assert!(m.replace(b"FIRE".to_vec(), 3250));
+        assert_eq!(m.window(), b"FIRE");
+        assert!(!m.replace(b"FIRE".to_vec(), 3300));
+    }
+
+    #[test]
+    fn test_rows_scroll_independently() {
+        let mut s = Scroller::new(2, 4, CONFIG);
+        let changed = s.set_text(&[b"abcdef".to_vec(), b"12".to_vec()], 0);
+        assert_eq!(changed, vec![0, 1]);
+        assert_eq!(s.deadline(), Some(1000));
+
+        assert_eq!(s.tick(1000), vec![0]);
+
+        // The short row updates without waiting on the long one
+        let changed = s.set_text(&[b"abcdef".to_vec(), b"34".to_vec()], 1050);
+        assert_eq!(changed, vec![1]);
+        assert_eq!(s.rows[1].window(), b"34  ");
+        assert_eq!(s.rows[0].window(), b"bcde");
+    }
+}
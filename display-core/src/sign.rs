@@ -0,0 +1,426 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    frame::{compose, Framebuffer},
+    proxy::Directives,
+    scroll::{ScrollConfig, Scroller},
+    CharDisplay, Clock,
+};
+
+// How long each half of a blink lasts
+pub const BLINK_MS: u64 = 500;
+
+// How much of the panel should be lit, as the proxy sends it
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Power {
+    #[default]
+    On,
+    // Backlight off, text still up. For quiet hours.
+    Dim,
+    // Nothing at all. For when the room's closed.
+    Off,
+}
+
+impl Power {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Power::On => "on",
+            Power::Dim => "dim",
+            Power::Off => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Power> {
+        match name {
+            "on" => Some(Power::On),
+            "dim" => Some(Power::Dim),
+            "off" => Some(Power::Off),
+            _ => None,
+        }
+    }
+}
+
+// A display, plus everything needed to keep it showing the right thing.
+// Hand it text whenever there's new text, and tick it often.
+pub struct Sign<D: CharDisplay> {
+    display: D,
+    scroller: Scroller,
+    frame: Framebuffer,
+    // Rows that differ from what's on the panel. Everything is to start
+    // with, since we don't know what the panel was left showing.
+    dirty: Vec<bool>,
+    // Blinking, and if so, which half of the blink we're in and since when
+    blink: bool,
+    visible: bool,
+    blink_since: u64,
+    power: Power,
+    // What the panel was last told. Panels come up on and lit.
+    shown: bool,
+    backlit: bool,
+    // Whether an emergency broadcast is up
+    interrupted: bool,
+}
+
+impl<D: CharDisplay> Sign<D> {
+    pub fn new(display: D, config: ScrollConfig) -> Self {
+        let (rows, cols) = (display.rows(), display.cols());
+        Sign {
+            display,
+            scroller: Scroller::new(rows, cols, config),
+            frame: Framebuffer::new(rows, cols),
+            dirty: vec![true; rows],
+            blink: false,
+            visible: true,
+            blink_since: 0,
+            power: Power::On,
+            shown: true,
+            backlit: true,
+            interrupted: false,
+        }
+    }
+
+    // Switch the panel on, off or somewhere in between. Takes effect on
+    // the next tick.
+    pub fn set_power(&mut self, power: Power) {
+        self.power = power;
+    }
+
+    // Start or stop blinking the whole display
+    pub fn set_blink(&mut self, blink: bool, now: u64) {
+        if blink && !self.blink {
+            self.blink_since = now;
+        }
+        self.blink = blink;
+    }
+
+    fn blink_deadline(&self) -> Option<u64> {
+        match (self.blink, self.visible) {
+            (true, _) => Some(self.blink_since + BLINK_MS),
+            // Stopped blinking while dark, so come back on right away
+            (false, false) => Some(self.blink_since),
+            (false, true) => None,
+        }
+    }
+
+    // New text, a line per row. Lines that haven't changed are left alone,
+    // so it's fine to call this with the same text over and over.
+    pub fn set_rows<S: AsRef<str>>(&mut self, lines: &[S], now: u64) {
+        let text = compose(lines, self.frame.rows());
+        for row in self.scroller.set_text(&text, now) {
+            self.stage(row);
+        }
+    }
+
+    // New text that goes up straight away, cutting off any rows partway
+    // through scrolling
+    pub fn interrupt<S: AsRef<str>>(&mut self, lines: &[S], now: u64) {
+        let text = compose(lines, self.frame.rows());
+        for row in self.scroller.replace(&text, now) {
+            self.stage(row);
+        }
+    }
+
+    // Go along with whatever the sign's been told. An emergency broadcast
+    // doesn't wait for a scrolling row to finish, and nothing dims it. Once
+    // it's cleared, the events go straight back up.
+    pub fn show(&mut self, directives: &Directives, now: u64) {
+        match &directives.emergency {
+            Some(lines) => {
+                self.interrupt(lines, now);
+                self.set_blink(true, now);
+                self.set_power(Power::On);
+                self.interrupted = true;
+            }
+            None => {
+                match self.interrupted {
+                    true => self.interrupt(&directives.rows, now),
+                    false => self.set_rows(&directives.rows, now),
+                }
+                self.interrupted = false;
+                self.set_blink(directives.flash, now);
+                self.set_power(directives.power);
+            }
+        }
+    }
+
+    // Keep the panel showing whatever `directives` says, checking at least
+    // every `idle_ms`. It gets the display, for anything else it wants to
+    // keep up to date. Only comes back if the panel can't be written to.
+    pub fn run<C: Clock>(
+        &mut self,
+        clock: &mut C,
+        idle_ms: u64,
+        mut directives: impl FnMut(&mut D) -> Directives,
+    ) -> Result<(), D::Error> {
+        loop {
+            let directives = directives(&mut self.display);
+            self.show(&directives, clock.now_ms());
+            self.tick(clock.now_ms())?;
+            self.wait(clock, idle_ms);
+        }
+    }
+
+    fn stage(&mut self, row: usize) {
+        let window = self.scroller.rows[row].window();
+        if self.frame.set_row(row, &window) {
+            self.dirty[row] = true;
+        }
+    }
+
+    // Step any scrolling rows, then push whatever changed out to the panel
+    pub fn tick(&mut self, now: u64) -> Result<(), D::Error> {
+        if self.blink_deadline().is_some_and(|d| now >= d) {
+            self.visible = !self.blink || !self.visible;
+            self.blink_since = now;
+        }
+        let visible = self.visible && self.power != Power::Off;
+        if visible != self.shown {
+            self.display.set_visible(visible)?;
+            self.shown = visible;
+        }
+        let backlit = self.power == Power::On;
+        if backlit != self.backlit {
+            self.display.set_backlight(backlit)?;
+            self.backlit = backlit;
+        }
+        for row in self.scroller.tick(now) {
+            self.stage(row);
+        }
+        for row in 0..self.frame.rows() {
+            if self.dirty[row] {
+                self.display.write_row(row, self.frame.row(row))?;
+                self.dirty[row] = false;
+            }
+        }
+        Ok(())
+    }
+
+    // Sleep until the next row needs to move, or for `max_ms` if that's
+    // sooner, so new text still gets picked up promptly.
+    pub fn wait<C: Clock>(&self, clock: &mut C, max_ms: u64) {
+        let now = clock.now_ms();
+        let deadline = [self.scroller.deadline(), self.blink_deadline()]
+            .into_iter()
+            .flatten()
+            .min();
+        let wait = match deadline {
+            Some(deadline) => deadline.saturating_sub(now).min(max_ms),
+            None => max_ms,
+        };
+        clock.sleep_ms(wait.max(10));
+    }
+
+    pub fn frame(&self) -> &Framebuffer {
+        &self.frame
+    }
+
+    pub fn display(&mut self) -> &mut D {
+        &mut self.display
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec, vec::Vec};
+    use core::convert::Infallible;
+
+    use crate::{
+        proxy::Directives,
+        scroll::ScrollConfig,
+        sign::{Power, Sign},
+        CharDisplay, Clock,
+    };
+
+    // Stands in for a panel. Keeps a copy of the whole screen after every
+    // write, so tests can see exactly what was drawn and when.
+    struct Recorder {
+        screen: Vec<String>,
+        frames: Vec<Vec<String>>,
+        // When the display went dark or came back, and which
+        blinks: Vec<bool>,
+        backlight: bool,
+    }
+
+    impl Recorder {
+        fn new(rows: usize, cols: usize) -> Self {
+            Recorder {
+                screen: vec![" ".repeat(cols); rows],
+                frames: vec![],
+                blinks: vec![],
+                backlight: true,
+            }
+        }
+    }
+
+    impl CharDisplay for Recorder {
+        type Error = Infallible;
+
+        fn rows(&self) -> usize {
+            self.screen.len()
+        }
+
+        fn cols(&self) -> usize {
+            self.screen[0].len()
+        }
+
+        fn write_row(&mut self, row: usize, text: &[u8]) -> Result<(), Infallible> {
+            self.screen[row] = text.iter().map(|&b| b as char).collect();
+            self.frames.push(self.screen.clone());
+            Ok(())
+        }
+
+        fn set_visible(&mut self, visible: bool) -> Result<(), Infallible> {
+            self.blinks.push(visible);
+            Ok(())
+        }
+
+        fn set_backlight(&mut self, on: bool) -> Result<(), Infallible> {
+            self.backlight = on;
+            Ok(())
+        }
+    }
+
+    struct FakeClock(u64);
+
+    impl Clock for FakeClock {
+        fn now_ms(&self) -> u64 {
+            self.0
+        }
+
+        fn sleep_ms(&mut self, ms: u64) {
+            self.0 += ms;
+        }
+    }
+
+    const CONFIG: ScrollConfig = ScrollConfig {
+        step_ms: 100,
+        dwell_ms: 1000,
+    };
+
+    #[test]
+    fn test_only_changed_rows_are_drawn() {
+        let mut sign = Sign::new(Recorder::new(2, 8), CONFIG);
+
+        // The first tick clears the whole panel
+        sign.set_rows(&["Lounge", "5m Left"], 0);
+        sign.tick(0).unwrap();
+        assert_eq!(sign.display().frames.len(), 2);
+        assert_eq!(sign.display().screen, ["Lounge  ", "5m Left "]);
+
+        sign.set_rows(&["Lounge", "5m Left"], 500);
+        sign.tick(500).unwrap();
+        assert_eq!(sign.display().frames.len(), 2);
+
+        sign.set_rows(&["Lounge", "4m Left"], 1000);
+        sign.tick(1000).unwrap();
+        assert_eq!(sign.display().frames.len(), 3);
+        assert_eq!(sign.display().screen[1], "4m Left ");
+    }
+
+    #[test]
+    fn test_scrolling_with_a_clock() {
+        let mut clock = FakeClock(0);
+        let mut sign = Sign::new(Recorder::new(2, 4), CONFIG);
+        sign.set_rows(&["abcdef", "12"], clock.now_ms());
+
+        // Dwell, two steps to the end, dwell, and back to the start
+        while clock.now_ms() <= 2100 {
+            sign.tick(clock.now_ms()).unwrap();
+            sign.wait(&mut clock, 250);
+        }
+        let top: Vec<&str> = sign
+            .display()
+            .frames
+            .iter()
+            .map(|f| f[0].as_str())
+            .collect();
+        assert_eq!(top, ["abcd", "abcd", "bcde", "cdef", "abcd"]);
+        assert_eq!(sign.display().screen[1], "12  ");
+
+        // Waits land right on each step, so the last one was at 2100
+        assert_eq!(clock.now_ms(), 2350);
+    }
+
+    #[test]
+    fn test_blink() {
+        let mut clock = FakeClock(0);
+        let mut sign = Sign::new(Recorder::new(2, 8), CONFIG);
+        sign.set_rows(&["Smash", "STARTING"], 0);
+        sign.set_blink(true, 0);
+
+        while clock.now_ms() <= 2000 {
+            sign.tick(clock.now_ms()).unwrap();
+            sign.wait(&mut clock, 250);
+        }
+        assert_eq!(sign.display().blinks, [false, true, false, true]);
+
+        // Stopping always leaves the display on, even halfway through
+        // the dark half of a blink
+        sign.set_blink(true, clock.now_ms());
+        sign.tick(clock.now_ms() + 500).unwrap();
+        assert_eq!(sign.display().blinks.last(), Some(&false));
+        sign.set_blink(false, clock.now_ms() + 600);
+        sign.tick(clock.now_ms() + 600).unwrap();
+        assert_eq!(sign.display().blinks.last(), Some(&true));
+        let count = sign.display().blinks.len();
+        sign.tick(clock.now_ms() + 5000).unwrap();
+        assert_eq!(sign.display().blinks.len(), count);
+    }
+
+    #[test]
+    fn test_power() {
+        let mut sign = Sign::new(Recorder::new(2, 8), CONFIG);
+        sign.set_rows(&["Lounge", "Closed"], 0);
+
+        sign.set_power(Power::Dim);
+        sign.tick(0).unwrap();
+        assert!(!sign.display().backlight);
+        assert!(sign.display().blinks.is_empty());
+
+        // Off stays off, even if something wants to blink
+        sign.set_power(Power::Off);
+        sign.set_blink(true, 0);
+        sign.tick(600).unwrap();
+        sign.tick(1200).unwrap();
+        assert_eq!(sign.display().blinks, [false]);
+
+        sign.set_power(Power::On);
+        sign.set_blink(false, 1200);
+        sign.tick(1800).unwrap();
+        assert!(sign.display().backlight);
+        assert_eq!(sign.display().blinks, [false, true]);
+
+        assert_eq!(Power::from_name(Power::Dim.name()), Some(Power::Dim));
+        assert_eq!(Power::from_name("bright"), None);
+    }
+
+    #[test]
+    fn test_emergency() {
+        let mut sign = Sign::new(Recorder::new(2, 8), CONFIG);
+        let mut directives = Directives {
+            rows: vec!["Lounge".into(), "Smash at 8pm".into()],
+            power: Power::Dim,
+            ..Default::default()
+        };
+        sign.show(&directives, 0);
+        sign.tick(0).unwrap();
+        assert!(!sign.display().backlight);
+
+        // Right over the scrolling row, lit and blinking
+        directives.emergency = Some(vec!["FIRE".into(), "ALARM".into()]);
+        sign.show(&directives, 1500);
+        sign.tick(1500).unwrap();
+        assert_eq!(sign.display().screen, ["FIRE    ", "ALARM   "]);
+        assert!(sign.display().backlight);
+        sign.tick(2000).unwrap();
+        assert_eq!(sign.display().blinks, [false]);
+
+        // And the events straight back once it's cleared
+        directives.emergency = None;
+        sign.show(&directives, 2100);
+        sign.tick(2100).unwrap();
+        assert_eq!(sign.display().screen, ["Lounge  ", "Smash at"]);
+        assert_eq!(sign.display().blinks, [false, true]);
+        assert!(!sign.display().backlight);
+    }
+}
@@ -0,0 +1,123 @@
+use embedded_hal::blocking::i2c;
+use esp_idf_hal::delay::FreeRtos;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+// Bits on the PCF8574 backpack, as wired on the 2004 boards we use
+const BACKLIGHT: u8 = 0b0000_1000;
+const ENABLE: u8 = 0b0000_0100;
+const REGISTER_SELECT: u8 = 0b0000_0001;
+
+/*
+ * A shared handle on the I2C bus to the LCD backpack.
+ *
+ * hd44780-driver owns its bus and doesn't let us send arbitrary commands,
+ * which we need for loading custom characters into CGRAM. So the driver
+ * gets one clone of this, and we keep another for talking to the
+ * controller directly.
+ *
+ * The backlight is just another pin on the expander, sent along with every
+ * nibble, so every clone shares one switch for it.
+ */
+pub struct Backpack<I2C: i2c::Write> {
+    i2c: Arc<Mutex<I2C>>,
+    address: u8,
+    backlight: Arc<AtomicBool>,
+}
+
+impl<I2C: i2c::Write> Clone for Backpack<I2C> {
+    fn clone(&self) -> Self {
+        Backpack {
+            i2c: Arc::clone(&self.i2c),
+            address: self.address,
+            backlight: Arc::clone(&self.backlight),
+        }
+    }
+}
+
+impl<I2C: i2c::Write> i2c::Write for Backpack<I2C> {
+    type Error = I2C::Error;
+
+    // hd44780-driver always sends the backlight bit, so it's taken back
+    // out here when the backlight is meant to be off
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        let bytes: Vec<u8> = match self.backlight.load(Ordering::Relaxed) {
+            true => bytes.to_vec(),
+            false => bytes.iter().map(|b| b & !BACKLIGHT).collect(),
+        };
+        self.i2c.lock().unwrap().write(address, &bytes)
+    }
+}
+
+impl<I2C: i2c::Write> Backpack<I2C> {
+    pub fn new(i2c: I2C, address: u8) -> Self {
+        Backpack {
+            i2c: Arc::new(Mutex::new(i2c)),
+            address,
+            backlight: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    // Another backpack on the same bus
+    pub fn with_address(&self, address: u8) -> Self {
+        Backpack {
+            i2c: Arc::clone(&self.i2c),
+            address,
+            backlight: Arc::clone(&self.backlight),
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    // The backlight switch. It takes effect with the next thing sent to
+    // the controller.
+    pub fn backlight(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.backlight)
+    }
+
+    // Send an instruction to the HD44780
+    pub fn command(&mut self, byte: u8) {
+        self.write_byte(byte, false);
+    }
+
+    // Write to whichever RAM the last instruction pointed at
+    pub fn data(&mut self, byte: u8) {
+        self.write_byte(byte, true);
+    }
+
+    // Store a 5x8 character in one of the 8 CGRAM slots. The controller
+    // is left addressing CGRAM, so move the cursor before writing text.
+    pub fn load_glyph(&mut self, slot: u8, glyph: &[u8; 8]) {
+        self.command(0b0100_0000 | ((slot & 0b111) << 3));
+        for row in glyph {
+            self.data(*row);
+        }
+    }
+
+    // The backpack only has four data lines, so bytes go over in halves
+    fn write_byte(&mut self, byte: u8, data: bool) {
+        self.write_nibble(byte & 0xF0, data);
+        self.write_nibble((byte & 0x0F) << 4, data);
+    }
+
+    fn write_nibble(&mut self, nibble: u8, data: bool) {
+        let rs = match data {
+            false => 0u8,
+            true => REGISTER_SELECT,
+        };
+        let backlight = match self.backlight.load(Ordering::Relaxed) {
+            false => 0u8,
+            true => BACKLIGHT,
+        };
+        let byte = nibble | rs | backlight;
+
+        let mut i2c = self.i2c.lock().unwrap();
+        let _ = i2c.write(self.address, &[byte, byte | ENABLE]);
+        FreeRtos::delay_ms(2);
+        let _ = i2c.write(self.address, &[byte]);
+    }
+}
@@ -10,98 +10,150 @@ use hd44780_driver::{
     bus::{DataBus, I2CBus},
     Cursor, CursorBlink, Display, DisplayMode, HD44780,
 };
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
 
-use log::info;
+use display_core::{
+    charset, proxy::Directives, scroll::ScrollConfig, sign::Sign, CharDisplay, Clock,
+};
 
-use crate::config::I2C_ADDR;
+use crate::{
+    bus::Backpack,
+    config::{COLS, I2C_ADDR, I2C_ADDR_2, ROWS, SCROLL_DWELL_MS, SCROLL_STEP_MS},
+};
 
 pub struct SidegradeDisplay<B: DataBus> {
-    pub lcd: HD44780<B>,
-    pub text: Vec<String>,
+    // One per HD44780 on the panel. Only 40x4s have more than one.
+    pub lcds: Vec<HD44780<B>>,
+    // Shared with the backpacks, which send it along with everything else
+    backlight: Arc<AtomicBool>,
 }
 
-#[derive(Clone)]
-enum LCDRow {
-    First = 0x00,
-    Second = 0x40,
-    Third = 0x14,
-    Fourth = 0x54,
+// 40x4 panels are really two 40x2s, each with its own controller
+const CONTROLLERS: usize = if ROWS == 4 && COLS == 40 { 2 } else { 1 };
+
+// Which controller drives a row, and where that row starts in its DDRAM.
+// The second row always starts at 0x40, and the third and fourth carry on
+// from where the first and second leave off (0x14 and 0x54 on a 2004).
+fn row_address(row: usize) -> (usize, u8) {
+    let line = (row % 2) as u8 * 0x40;
+    match CONTROLLERS {
+        1 => (0, line + (row / 2 * COLS) as u8),
+        _ => (row / 2, line),
+    }
 }
 
 impl<B: DataBus> SidegradeDisplay<B> {
     // Gross, yet convenient methods
     pub fn wipe(&mut self) {
-        self.lcd.reset(&mut Ets).unwrap();
-        self.lcd.clear(&mut Ets).unwrap();
+        for lcd in self.lcds.iter_mut() {
+            lcd.reset(&mut Ets).unwrap();
+            lcd.clear(&mut Ets).unwrap();
+        }
     }
 
     pub fn write(&mut self, string: &str) {
-        self.lcd.write_str(string, &mut Ets).unwrap();
+        self.lcds[0].write_str(string, &mut Ets).unwrap();
     }
 
     pub fn flash(&mut self, count: u32, hz: u32) {
         for _ in 0..count {
-            let _ = self.lcd.set_display_mode(
-                DisplayMode {
-                    display: Display::Off,
-                    cursor_visibility: Cursor::Invisible,
-                    cursor_blink: CursorBlink::Off,
-                },
-                &mut Ets,
-            );
+            let _ = self.set_visible(false);
             FreeRtos::delay_us(hz);
-            let _ = self.lcd.set_display_mode(
+            let _ = self.set_visible(true);
+            FreeRtos::delay_us(hz);
+        }
+    }
+
+    pub fn run(self, directives: Arc<Mutex<Directives>>) -> anyhow::Result<()> {
+        // Every row scrolls on its own, so a long title on one row doesn't
+        // hold up the countdown on another.
+        let mut sign = Sign::new(
+            self,
+            ScrollConfig {
+                step_ms: SCROLL_STEP_MS,
+                dwell_ms: SCROLL_DWELL_MS,
+            },
+        );
+        sign.run(&mut EspClock::new(), IDLE_MS, |_| {
+            directives.lock().unwrap().clone()
+        })
+        .map_err(|_| anyhow::anyhow!("Could not write to display"))
+    }
+}
+
+// How often to look for new text when nothing is scrolling
+const IDLE_MS: u64 = 250;
+
+impl<B: DataBus> CharDisplay for SidegradeDisplay<B> {
+    type Error = hd44780_driver::error::Error;
+
+    fn rows(&self) -> usize {
+        ROWS
+    }
+
+    fn cols(&self) -> usize {
+        COLS
+    }
+
+    fn write_row(&mut self, row: usize, text: &[u8]) -> Result<(), Self::Error> {
+        let (lcd, address) = row_address(row);
+        let lcd = &mut self.lcds[lcd];
+        lcd.set_cursor_pos(address, &mut Ets)?;
+        lcd.write_bytes(text, &mut Ets)
+    }
+
+    fn set_visible(&mut self, visible: bool) -> Result<(), Self::Error> {
+        for lcd in self.lcds.iter_mut() {
+            lcd.set_display_mode(
                 DisplayMode {
-                    display: Display::Off,
+                    display: if visible { Display::On } else { Display::Off },
                     cursor_visibility: Cursor::Invisible,
                     cursor_blink: CursorBlink::Off,
                 },
                 &mut Ets,
-            );
+            )?;
         }
+        Ok(())
     }
 
-    pub fn run(&mut self, m: Arc<Mutex<Vec<String>>>) -> anyhow::Result<()> {
-        // Create a position vector and a finished vector for each line
-        let mut l_pos = [0; 4];
-        let mut l_fin = [false; 4];
-        let row = vec![LCDRow::First, LCDRow::Second, LCDRow::Third, LCDRow::Fourth];
-
-        loop {
-            for (idx, line) in self.text.iter().enumerate() {
-                // If the line length is >20, then step the line
-                if line.len() > 20 {
-                    let mut t: String = line.chars().skip(l_pos[idx]).take(20).collect();
-                    t = format!("{: <20}", t);
-                    let _ = self.lcd.set_cursor_pos(row[idx].clone() as u8, &mut Ets);
-                    let _ = self.lcd.write_str(&t, &mut Ets);
-
-                    if l_pos[idx] > line.len() - 16 {
-                        l_pos[idx] = 0;
-                        l_fin[idx] = true;
-                    } else {
-                        l_pos[idx] += 4;
-                    }
-                } else {
-                    let t = format!("{: <20}", &line);
-                    let _ = self.lcd.set_cursor_pos(row[idx].clone() as u8, &mut Ets);
-                    let _ = self.lcd.write_str(&t, &mut Ets);
-                    l_fin[idx] = true;
-                }
-            }
-            FreeRtos::delay_ms(1000);
-            if l_fin.iter().all(|&x| x) {
-                {
-                    info!("Update display");
-                    let screen = m.lock().unwrap();
-                    self.text = (*screen.clone()).to_vec();
-                    if self.text.len() < 4 {
-                        self.text.resize(4, String::new());
-                    }
-                }
-            }
+    fn set_backlight(&mut self, on: bool) -> Result<(), Self::Error> {
+        self.backlight.store(on, Ordering::Relaxed);
+        // The backpack only changes its pins when it's sent something, so
+        // send something harmless. Rows get written with their own
+        // addresses anyway.
+        for lcd in self.lcds.iter_mut() {
+            lcd.set_cursor_pos(0, &mut Ets)?;
         }
+        Ok(())
+    }
+}
+
+// Milliseconds since boot, and FreeRTOS to sleep on
+pub(crate) struct EspClock {
+    start: Instant,
+}
+
+impl EspClock {
+    pub(crate) fn new() -> Self {
+        EspClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for EspClock {
+    fn now_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn sleep_ms(&mut self, ms: u64) {
+        FreeRtos::delay_ms(ms as u32);
     }
 }
 
@@ -110,26 +162,44 @@ impl<'d, I2C: i2c::Write> SidegradeDisplay<I2CBus<I2C>> {
         i2c: impl Peripheral<P = I> + 'd,
         sda: impl Peripheral<P = impl InputPin + OutputPin> + 'd,
         scl: impl Peripheral<P = impl InputPin + OutputPin> + 'd,
-    ) -> anyhow::Result<SidegradeDisplay<I2CBus<I2cDriver<'d>>>> {
+    ) -> anyhow::Result<SidegradeDisplay<I2CBus<Backpack<I2cDriver<'d>>>>> {
         let config = I2cConfig::new().baudrate(100.kHz().into());
         let i2c_driver = I2cDriver::new(i2c, sda, scl, &config)?;
-        let mut lcd = HD44780::new_i2c(i2c_driver, I2C_ADDR, &mut Ets).unwrap();
-
-        // Set up the display
-        let _ = lcd.reset(&mut Ets);
-        let _ = lcd.clear(&mut Ets);
-        let _ = lcd.set_display_mode(
-            DisplayMode {
-                display: Display::On,
-                cursor_visibility: Cursor::Invisible,
-                cursor_blink: CursorBlink::Off,
-            },
-            &mut Ets,
-        );
+        let backpack = Backpack::new(i2c_driver, I2C_ADDR);
+        let backlight = backpack.backlight();
 
-        Ok(SidegradeDisplay {
-            lcd,
-            text: vec![String::new(); 4],
-        })
+        // The second controller on a 40x4 gets its own backpack
+        let backpacks = match CONTROLLERS {
+            1 => vec![backpack],
+            _ => vec![backpack.clone(), backpack.with_address(I2C_ADDR_2)],
+        };
+
+        let mut lcds = vec![];
+        for mut backpack in backpacks {
+            let mut lcd = HD44780::new_i2c(backpack.clone(), backpack.address(), &mut Ets).unwrap();
+
+            // Set up the display
+            let _ = lcd.reset(&mut Ets);
+            let _ = lcd.clear(&mut Ets);
+            let _ = lcd.set_display_mode(
+                DisplayMode {
+                    display: Display::On,
+                    cursor_visibility: Cursor::Invisible,
+                    cursor_blink: CursorBlink::Off,
+                },
+                &mut Ets,
+            );
+
+            // Load our custom characters, then point the controller back at
+            // the screen.
+            for (slot, glyph) in charset::GLYPHS.iter().enumerate() {
+                backpack.load_glyph(slot as u8, glyph);
+            }
+            let _ = lcd.set_cursor_pos(0, &mut Ets);
+
+            lcds.push(lcd);
+        }
+
+        Ok(SidegradeDisplay { lcds, backlight })
     }
 }
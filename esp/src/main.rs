@@ -1,6 +1,6 @@
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use embedded_svc::{
-    http::{client::Client as HttpClient, Method},
+    http::{client::Client as HttpClient, Headers, Method},
     utils::io,
     wifi::{AuthMethod, ClientConfiguration, Configuration},
 };
@@ -17,17 +17,24 @@ use esp_idf_svc::{
 
 use hd44780_driver::bus::I2CBus;
 
+use display_core::{
+    proxy::{self, Directives, Response},
+    sign::Power,
+};
+
 use log::{error, info, warn};
 
 use std::sync::{Arc, Mutex};
 
 use futures::executor::block_on;
 
+pub mod bus;
 pub mod config;
 pub mod display;
 
 use crate::{
-    config::{HZ, PASSWORD, PROXY_ROUTE, SSID},
+    bus::Backpack,
+    config::{COLS, EMERGENCY_POLL_MS, EMERGENCY_ROUTE, HZ, PASSWORD, PROXY_ROUTE, ROWS, SSID},
     display::*,
 };
 
@@ -51,7 +58,7 @@ fn main() -> anyhow::Result<()> {
     let i2c = peripherals.i2c1;
     let sda = peripherals.pins.gpio33;
     let scl = peripherals.pins.gpio32;
-    let mut lcd = SidegradeDisplay::<I2CBus<I2cDriver>>::new_i2c(i2c, sda, scl)?;
+    let mut lcd = SidegradeDisplay::<I2CBus<Backpack<I2cDriver>>>::new_i2c(i2c, sda, scl)?;
 
     // Connect to Wifi
     lcd.write("Connecting...");
@@ -85,9 +92,11 @@ fn main() -> anyhow::Result<()> {
     lcd.write("Query Proxy...");
 
     // Shared data so that the proxy thread can update the display thread
-    let screen_updates = Arc::new(Mutex::new(vec![String::new(); 4]));
-    let lcd_screen_updates = Arc::clone(&screen_updates);
-    let query_screen_updates = Arc::clone(&screen_updates);
+    let directives = Arc::new(Mutex::new(Directives {
+        rows: vec![String::new(); ROWS],
+        ..Default::default()
+    }));
+    let lcd_directives = Arc::clone(&directives);
 
     /*
      * I suppose this is the bonafide main thread.
@@ -104,15 +113,25 @@ fn main() -> anyhow::Result<()> {
         .spawn(move || -> anyhow::Result<()> {
             loop {
                 // GET
+                let mut delay = HZ;
                 let proxy_response = query_proxy();
                 match proxy_response {
                     Ok(r) => {
                         info!("Proxy query successful.");
-                        let mut screen = query_screen_updates.lock().unwrap();
-                        *screen = r.split('\n').map(String::from).collect();
+                        delay = r.delay(HZ as u64) as u32;
+                        let mut directives = directives.lock().unwrap();
+                        directives.rows = r.rows;
+                        directives.flash = r.flash;
+                        directives.power = r.power;
                     }
                     Err(e) => {
                         error!("Proxy Thread Error: {}", e);
+                        {
+                            let mut directives = directives.lock().unwrap();
+                            directives.flash = true;
+                            // Stay lit, so someone notices
+                            directives.power = Power::On;
+                        }
                         // Spaghetti. If you see ESP_ERR_HTTP_CONNECT, then try
                         // Re-connecting to the WiFi
                         if
@@ -121,8 +140,8 @@ fn main() -> anyhow::Result<()> {
                             loop {
                                 info!("Connecting WiFi...");
                                 {
-                                    let mut screen = query_screen_updates.lock().unwrap();
-                                    *screen = vec![
+                                    let mut directives = directives.lock().unwrap();
+                                    directives.rows = vec![
                                         "ESP_ERR_HTTP_CONNECT".to_string(),
                                         "Re-connecting...".to_string(),
                                         "".to_string(),
@@ -140,8 +159,8 @@ fn main() -> anyhow::Result<()> {
                             }
                         } else {
                             {
-                                let mut screen = query_screen_updates.lock().unwrap();
-                                *screen = vec![
+                                let mut directives = directives.lock().unwrap();
+                                directives.rows = vec![
                                     "Could not fetch updates.".to_string(),
                                     e.to_string(),
                                     "Check Proxy?".to_string(),
@@ -152,7 +171,7 @@ fn main() -> anyhow::Result<()> {
                         }
                     }
                 }
-                FreeRtos::delay_ms(HZ);
+                watch_emergency(delay, &directives);
             }
         });
 
@@ -162,7 +181,7 @@ fn main() -> anyhow::Result<()> {
     let lcd_thread = std::thread::Builder::new()
         .name("display".to_string())
         .stack_size(7000)
-        .spawn(move || -> anyhow::Result<()> { lcd.run(lcd_screen_updates) });
+        .spawn(move || -> anyhow::Result<()> { lcd.run(lcd_directives) });
 
     lcd_thread?.join().unwrap()?;
     proxy_thread?.join().unwrap()?;
@@ -197,7 +216,60 @@ async fn connect_wifi(wifi: &mut AsyncWifi<EspWifi<'static>>) -> anyhow::Result<
     Ok(())
 }
 
-fn query_proxy() -> anyhow::Result<String> {
+// Wait out the delay until the next query, checking for an emergency
+// broadcast every EMERGENCY_POLL_MS so one shows up within seconds
+fn watch_emergency(delay: u32, directives: &Mutex<Directives>) {
+    if EMERGENCY_ROUTE.is_empty() {
+        FreeRtos::delay_ms(delay);
+        return;
+    }
+    proxy::watch_emergency(
+        &mut EspClock::new(),
+        delay as u64,
+        EMERGENCY_POLL_MS as u64,
+        || {
+            query_emergency().map_err(|e| {
+                warn!("Could not check for emergencies: {}", e);
+                e
+            })
+        },
+        |alert| {
+            let mut directives = directives.lock().unwrap();
+            match (&directives.emergency, &alert) {
+                (None, Some(_)) => warn!("Emergency broadcast!"),
+                (Some(_), None) => info!("Emergency broadcast cleared."),
+                _ => (),
+            }
+            std::mem::replace(&mut directives.emergency, alert)
+        },
+    );
+}
+
+// The broadcast's rows, or None when there isn't one going
+fn query_emergency() -> anyhow::Result<Option<Vec<String>>> {
+    let mut client = HttpClient::wrap(EspHttpConnection::new(&Default::default())?);
+    let headers = [("accept", "text/plain")];
+    let separator = if EMERGENCY_ROUTE.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let url = format!(
+        "{}{}rows={}&cols={}",
+        EMERGENCY_ROUTE, separator, ROWS, COLS
+    );
+    let mut response = client.request(Method::Get, &url, &headers)?.submit()?;
+    let status = response.status();
+    let mut buf = [0u8; 256];
+    let bytes_read = io::try_read_full(&mut response, &mut buf).map_err(|e| e.0)?;
+    match std::str::from_utf8(&buf[0..bytes_read]) {
+        Ok(body_string) => proxy::broadcast(status, body_string)
+            .map_err(|status| anyhow!("Unexpected status {}", status)),
+        Err(e) => bail!("Error decoding response body: {}", e),
+    }
+}
+
+fn query_proxy() -> anyhow::Result<Response> {
     // Create HTTP(S) client
     let mut client = HttpClient::wrap(EspHttpConnection::new(&Default::default())?);
     // Prepare headers and URL
@@ -206,8 +278,11 @@ fn query_proxy() -> anyhow::Result<String> {
     // Send request
     //
     // Note: If you don't want to pass in any headers, you can also use `client.get(url, headers)`.
-    let request = client.request(Method::Get, PROXY_ROUTE, &headers)?;
-    info!("-> GET {}", PROXY_ROUTE);
+    // Let the proxy know what size display it's drawing for
+    let separator = if PROXY_ROUTE.contains('?') { '&' } else { '?' };
+    let url = format!("{}{}rows={}&cols={}", PROXY_ROUTE, separator, ROWS, COLS);
+    let request = client.request(Method::Get, &url, &headers)?;
+    info!("-> GET {}", url);
     let mut response = request.submit()?;
 
     // Process response
@@ -223,7 +298,7 @@ fn query_proxy() -> anyhow::Result<String> {
                 buf.len(),
                 body_string
             );
-            Ok(body_string.to_string())
+            Ok(Response::parse(body_string, |name| response.header(name)))
         }
         Err(e) => bail!("Error decoding response body: {}", e),
     }
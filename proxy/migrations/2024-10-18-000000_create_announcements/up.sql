@@ -0,0 +1,16 @@
+CREATE TABLE announcements (
+    id SERIAL PRIMARY KEY,
+    -- 'all', 'location' or 'group'
+    scope TEXT NOT NULL,
+    -- The location or group, unless it's for everyone
+    target TEXT,
+    message TEXT NOT NULL,
+    starts_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0,
+    -- 'preempt' or 'ticker'
+    mode TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX announcements_expires_at ON announcements (expires_at);
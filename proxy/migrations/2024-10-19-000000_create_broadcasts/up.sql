@@ -0,0 +1,7 @@
+-- Live until an admin clears it, and then kept as a record
+CREATE TABLE broadcasts (
+    id SERIAL PRIMARY KEY,
+    message TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    cleared_at TIMESTAMPTZ
+);
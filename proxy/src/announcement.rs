@@ -0,0 +1,382 @@
+// Notices posted by whoever runs the house, like house meetings, fire
+// drills and "Lounge closed for cleaning". They live in Postgres, go to one
+// location, a group or every sign, and either take over the screen or run
+// along one row of it.
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use diesel::{
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, env};
+
+use crate::calendar::Timeline;
+use crate::config::{Config, LocationConfig};
+use crate::power::{self, Power};
+use crate::render::{self, Geometry, Screen};
+use crate::schema::announcements;
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+// Between tickers that share a row
+const TICKER_SEPARATOR: &str = " +++ ";
+
+// How an announcement shows up on a sign
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    // Instead of the events
+    #[default]
+    Preempt,
+    // On the last row, under the events
+    Ticker,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Preempt => "preempt",
+            Mode::Ticker => "ticker",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Serialize)]
+#[diesel(table_name = announcements)]
+pub struct Announcement {
+    pub id: i32,
+    // "all", "location" or "group"
+    pub scope: String,
+    pub target: Option<String>,
+    pub message: String,
+    pub starts_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub priority: i32,
+    pub mode: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// A sign that announcements can be for
+#[derive(Debug, Clone, Copy)]
+pub enum Audience<'a> {
+    Location(&'a str),
+    Group(&'a str),
+}
+
+impl Announcement {
+    pub fn mode(&self) -> Mode {
+        match self.mode.as_str() {
+            "ticker" => Mode::Ticker,
+            _ => Mode::Preempt,
+        }
+    }
+
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at <= now && now < self.expires_at
+    }
+
+    // Whether a sign should show this. Announcements for a group go to
+    // the signs in each of its rooms, as well as the group's own.
+    pub fn reaches(&self, audience: Audience, config: &Config) -> bool {
+        let target = self.target.as_deref().unwrap_or_default();
+        match (self.scope.as_str(), audience) {
+            ("all", _) => true,
+            ("location", Audience::Location(l)) => target.eq_ignore_ascii_case(l),
+            ("group", Audience::Group(g)) => target.eq_ignore_ascii_case(g),
+            ("group", Audience::Location(l)) => config
+                .group(target)
+                .is_some_and(|g| g.locations.iter().any(|m| m.eq_ignore_ascii_case(l))),
+            _ => false,
+        }
+    }
+}
+
+// What gets posted to the API
+#[derive(Debug, Deserialize)]
+pub struct AnnouncementRequest {
+    pub message: String,
+    // One or the other, or neither for every sign
+    pub location: Option<String>,
+    pub group: Option<String>,
+    // Now, if it's left out
+    pub starts_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub mode: Mode,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = announcements)]
+pub struct NewAnnouncement {
+    scope: String,
+    target: Option<String>,
+    message: String,
+    starts_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    priority: i32,
+    mode: String,
+}
+
+impl AnnouncementRequest {
+    pub fn validate(self, now: DateTime<Utc>) -> Result<NewAnnouncement, String> {
+        if self.message.trim().is_empty() {
+            return Err("An announcement needs a message".to_string());
+        }
+        let starts_at = self.starts_at.unwrap_or(now);
+        if self.expires_at <= starts_at {
+            return Err("An announcement has to expire after it starts".to_string());
+        }
+        let (scope, target) = match (self.location, self.group) {
+            (None, None) => ("all", None),
+            (Some(location), None) => ("location", Some(location)),
+            (None, Some(group)) => ("group", Some(group)),
+            (Some(_), Some(_)) => {
+                return Err("Pick a location or a group, not both".to_string());
+            }
+        };
+        Ok(NewAnnouncement {
+            scope: scope.to_string(),
+            target,
+            message: self.message,
+            starts_at,
+            expires_at: self.expires_at,
+            priority: self.priority,
+            mode: self.mode.name().to_string(),
+        })
+    }
+}
+
+// Put whatever's live for a sign on its screen. The highest priority
+// announcement that preempts takes the whole screen. Failing that, every
+// live ticker shares the last row, highest priority first.
+pub fn apply(
+    screen: &mut Screen,
+    announcements: &[&Announcement],
+    now: DateTime<Utc>,
+    geometry: Geometry,
+) {
+    // Check back when the next one starts or the live ones expire
+    for a in announcements {
+        let change = match a.starts_at > now {
+            true => a.starts_at,
+            false => a.expires_at,
+        };
+        screen.refresh_at(change, now);
+    }
+
+    let mut live: Vec<&Announcement> = announcements
+        .iter()
+        .filter(|a| a.is_live(now))
+        .cloned()
+        .collect();
+    live.sort_by_key(|a| (Reverse(a.priority), Reverse(a.starts_at)));
+
+    if let Some(a) = live.iter().find(|a| a.mode() == Mode::Preempt) {
+        screen.rows = a.message.lines().map(String::from).collect();
+        screen.page = None;
+        screen.label = None;
+        // Nobody reads a notice on a sign that's switched off
+        screen.power = Power::On;
+        return;
+    }
+
+    let tickers: Vec<String> = live
+        .iter()
+        .filter(|a| a.mode() == Mode::Ticker)
+        .map(|a| a.message.replace('\n', " "))
+        .collect();
+    if !tickers.is_empty() {
+        screen
+            .rows
+            .resize(geometry.content_rows() - 1, String::new());
+        screen.rows.push(tickers.join(TICKER_SEPARATOR));
+    }
+}
+
+// Every live announcement, a page at a time, for playlists that give them
+// a turn of their own
+pub fn board(
+    announcements: &[&Announcement],
+    timeline: &Timeline,
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    geometry: Geometry,
+) -> Screen {
+    let mut live: Vec<&Announcement> = announcements
+        .iter()
+        .filter(|a| a.is_live(now))
+        .cloned()
+        .collect();
+    live.sort_by_key(|a| (Reverse(a.priority), Reverse(a.starts_at)));
+    let rows = live
+        .iter()
+        .flat_map(|a| a.message.lines().map(String::from))
+        .collect();
+    let mut screen = render::paged(rows, geometry.content_rows(), now, location.page_seconds);
+    screen.power = power::power(&[timeline], now, location);
+    screen
+}
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+// Where announcements are kept. Without a database, there just aren't any.
+// Broadcasts share its connections, and countdowns live in a file of their
+// own.
+#[derive(Default)]
+pub struct Store {
+    pub(crate) pool: Option<DbPool>,
+}
+
+impl Store {
+    // Connect to DATABASE_URL, or put one together from the same DB_*
+    // variables HACKING/docker-compose.yaml uses, and bring the schema up
+    // to date
+    pub fn connect() -> anyhow::Result<Self> {
+        let url = match env::var("DATABASE_URL") {
+            Ok(url) => url,
+            Err(_) => match (
+                env::var("DB_USER"),
+                env::var("DB_PASSWORD"),
+                env::var("DB_NAME"),
+            ) {
+                (Ok(user), Ok(password), Ok(name)) => {
+                    let host = env::var("DB_HOST").unwrap_or("localhost".to_string());
+                    format!("postgres://{}:{}@{}/{}", user, password, host, name)
+                }
+                _ => {
+                    println!("No database configured, announcements are off");
+                    return Ok(Store::default());
+                }
+            },
+        };
+
+        let pool = Pool::builder().build(ConnectionManager::<PgConnection>::new(url))?;
+        pool.get()?
+            .run_pending_migrations(MIGRATIONS)
+            .map_err(|e| anyhow!("Could not migrate database: {}", e))?;
+        Ok(Store { pool: Some(pool) })
+    }
+
+    fn pool(&self) -> anyhow::Result<&DbPool> {
+        self.pool
+            .as_ref()
+            .ok_or(anyhow!("No database configured for announcements"))
+    }
+
+    // Everything that hasn't expired yet, including what's yet to start
+    pub fn current(&self, now: DateTime<Utc>) -> anyhow::Result<Vec<Announcement>> {
+        let pool = match &self.pool {
+            Some(pool) => pool,
+            None => return Ok(vec![]),
+        };
+        let found = announcements::table
+            .filter(announcements::expires_at.gt(now))
+            .order(announcements::starts_at)
+            .select(Announcement::as_select())
+            .load(&mut pool.get()?)?;
+        Ok(found)
+    }
+
+    pub fn create(&self, new: NewAnnouncement) -> anyhow::Result<Announcement> {
+        let created = diesel::insert_into(announcements::table)
+            .values(&new)
+            .returning(Announcement::as_returning())
+            .get_result(&mut self.pool()?.get()?)?;
+        Ok(created)
+    }
+
+    // Whether there was anything to delete
+    pub fn delete(&self, id: i32) -> anyhow::Result<bool> {
+        let deleted =
+            diesel::delete(announcements::table.find(id)).execute(&mut self.pool()?.get()?)?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::announcement::{apply, Announcement, Audience, Mode};
+    use crate::config::Config;
+    use crate::render::{Geometry, Screen};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    fn announcement(message: &str, mode: Mode, priority: i32, hours: (u32, u32)) -> Announcement {
+        let at = |h| Utc.with_ymd_and_hms(2020, 1, 1, h, 0, 0).unwrap();
+        Announcement {
+            id: 1,
+            scope: "all".to_string(),
+            target: None,
+            message: message.to_string(),
+            starts_at: at(hours.0),
+            expires_at: at(hours.1),
+            priority,
+            mode: mode.name().to_string(),
+            created_at: at(0),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let events = Screen::from_rows(vec!["Smash".to_string(), "In 00:15:00".to_string()]);
+        let meeting = announcement(
+            "House meeting\n8pm in the Lounge",
+            Mode::Preempt,
+            0,
+            (18, 20),
+        );
+        let drill = announcement("FIRE DRILL", Mode::Preempt, 10, (19, 20));
+        let cleaning = announcement("Lounge closed for cleaning", Mode::Ticker, 0, (12, 22));
+        let all = [&meeting, &drill, &cleaning];
+        let at = |h, m| Utc.with_ymd_and_hms(2020, 1, 1, h, m, 0).unwrap();
+
+        // Only tickers, so the events keep the rows above
+        let mut screen = events.clone();
+        apply(&mut screen, &all, at(17, 0), Geometry::default());
+        assert_eq!(
+            screen.rows,
+            ["Smash", "In 00:15:00", "Lounge closed for cleaning"]
+        );
+        assert_eq!(screen.refresh, Some(Duration::hours(1)));
+        let mut screen = events.clone();
+        apply(&mut screen, &all, at(17, 0), Geometry::new(2, 16).unwrap());
+        assert_eq!(screen.rows, ["Smash", "Lounge closed for cleaning"]);
+
+        // The highest priority takeover wins
+        let mut screen = events.clone();
+        apply(&mut screen, &all, at(18, 30), Geometry::default());
+        assert_eq!(screen.rows, ["House meeting", "8pm in the Lounge"]);
+        let mut screen = events.clone();
+        apply(&mut screen, &all, at(19, 30), Geometry::default());
+        assert_eq!(screen.rows, ["FIRE DRILL"]);
+
+        // and once everything's expired, it's just events again
+        let mut screen = events.clone();
+        apply(&mut screen, &all, at(22, 0), Geometry::default());
+        assert_eq!(screen, events);
+    }
+
+    #[test]
+    fn test_reaches() {
+        let config: Config =
+            serde_json::from_str(include_str!("../locations.json.sample")).unwrap();
+        let mut a = announcement("Hi", Mode::Ticker, 0, (0, 1));
+        assert!(a.reaches(Audience::Location("Attic"), &config));
+
+        a.scope = "group".to_string();
+        a.target = Some("Hallway".to_string());
+        assert!(a.reaches(Audience::Group("hallway"), &config));
+        assert!(a.reaches(Audience::Location("Library"), &config));
+        assert!(!a.reaches(Audience::Location("Attic"), &config));
+
+        a.scope = "location".to_string();
+        a.target = Some("Lounge".to_string());
+        assert!(a.reaches(Audience::Location("Lounge"), &config));
+        assert!(!a.reaches(Audience::Group("Hallway"), &config));
+    }
+}
@@ -1,9 +1,18 @@
 use anyhow::anyhow;
 use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
 use serde::Deserialize;
 use std::env;
 use url::form_urlencoded;
 
+use crate::change::{self, Change};
+use crate::config::LocationConfig;
+use crate::countdown;
+use crate::metadata::{ExtendedProperties, SignOptions};
+
+// How many minutes back to ask the calendar for events
+const RECENTLY_ENDED: i64 = 15;
+
 // Struct that fits the dateTime field of the Google Calendar API
 // response
 #[derive(Debug, Deserialize, Clone)]
@@ -19,28 +28,43 @@ pub struct EventTimeInfo {
 // API response
 #[derive(Debug, Deserialize, Clone)]
 pub struct Event {
+    pub id: Option<String>,
     pub summary: String,
     pub description: Option<String>,
     pub location: Option<String>,
     pub start: EventTimeInfo,
     pub end: EventTimeInfo,
+    #[serde(rename = "extendedProperties")]
+    pub extended_properties: Option<ExtendedProperties>,
+    // "default", "public", "private" or "confidential"
+    pub visibility: Option<String>,
+    pub organizer: Option<Organizer>,
+}
+
+// Who owns an event. For events on shared calendars, it's the calendar
+// they were booked from.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Organizer {
+    pub email: Option<String>,
 }
 
 impl Event {
-    pub fn format_2004(&self) -> String {
+    pub fn format_2004(&self, now: DateTime<Utc>, config: &LocationConfig) -> String {
+        let title = self.title();
+        let mut text = title.clone();
         if let Some(start_time) = &self.start.date_time {
-            let duration_until = Self::time_until(start_time);
+            let duration_until = Self::time_until(start_time, now);
             if duration_until > Duration::zero() {
-                let t = Self::format_duration(duration_until);
-                return format!("{}\nIn {}", self.summary, t);
+                let t = countdown::starts_in(duration_until, *start_time, config);
+                text = format!("{}\n{}", title, t);
             } else {
                 // If that didn't work, then the event is probably already going.
                 // Check if we can get the time until.
                 if let Some(end_time) = &self.end.date_time {
-                    let duration_until = Self::time_until(end_time);
+                    let duration_until = Self::time_until(end_time, now);
                     if duration_until > Duration::zero() {
-                        let t = Self::format_duration(duration_until);
-                        return format!("{}\n{} Left", self.summary, t);
+                        let t = countdown::time_left(duration_until, config);
+                        text = format!("{}\n{}", title, t);
                     }
                 }
             }
@@ -48,26 +72,103 @@ impl Event {
 
         // If we don't have any datetime info, then
         // just return the title of the event
-        self.summary.clone()
+        match self.sign().line {
+            Some(line) => format!("{}\n{}", text, line),
+            None => text,
+        }
+    }
+
+    // What the organizer wants the signs to do with this event
+    pub fn sign(&self) -> SignOptions {
+        SignOptions::parse(
+            self.description.as_deref(),
+            self.extended_properties.as_ref(),
+        )
+    }
+
+    // The event's name as the signs show it
+    pub fn title(&self) -> String {
+        self.sign().title.unwrap_or(self.summary.clone())
     }
 
-    fn time_until(timestamp: &DateTime<Utc>) -> Duration {
+    // Whether the organizer marked the event as not for everyone's eyes
+    pub fn is_private(&self) -> bool {
+        matches!(
+            self.visibility.as_deref(),
+            Some("private") | Some("confidential")
+        )
+    }
+
+    pub fn is_happening(&self, now: DateTime<Utc>) -> bool {
+        match (self.start.date_time, self.end.date_time) {
+            (Some(start), Some(end)) => start <= now && now < end,
+            _ => false,
+        }
+    }
+
+    fn time_until(timestamp: &DateTime<Utc>, now: DateTime<Utc>) -> Duration {
         // let parsed_timestamp = DateTime::parse_from_rfc3339(timestamp)
         //     .expect("Failed to parse timestamp")
         //     .with_timezone(&Utc);
 
-        let current_time = Utc::now();
+        timestamp.signed_duration_since(now)
+    }
+}
+
+// The events at one location, ordered by start time
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    pub events: Vec<Event>,
+}
+
+impl Timeline {
+    // The event going on right now, if there is one
+    pub fn current(&self, now: DateTime<Utc>) -> Option<&Event> {
+        self.events.iter().find(|e| e.is_happening(now))
+    }
 
-        timestamp.signed_duration_since(current_time)
+    // The first event that hasn't started yet
+    pub fn next(&self, now: DateTime<Utc>) -> Option<&Event> {
+        self.events
+            .iter()
+            .find(|e| e.start.date_time.is_some_and(|s| s > now))
+    }
+
+    // If the room is in use, when it actually frees up. Back-to-back (or
+    // overlapping) events are treated as one long booking.
+    pub fn busy_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut until = self.current(now)?.end.date_time?;
+        for e in &self.events {
+            if let (Some(start), Some(end)) = (e.start.date_time, e.end.date_time) {
+                if start <= until && end > until {
+                    until = end;
+                }
+            }
+        }
+        Some(until)
     }
 
-    fn format_duration(duration: Duration) -> String {
-        let seconds = duration.num_seconds();
-        let hours = seconds / 3600;
-        let minutes = (seconds % 3600) / 60;
-        let remaining_seconds = seconds % 60;
+    // What's left of today's schedule: anything going on now, plus anything
+    // that starts before midnight local time
+    pub fn today(&self, now: DateTime<Utc>, tz: Tz) -> Vec<&Event> {
+        let today = now.with_timezone(&tz).date_naive();
+        self.events
+            .iter()
+            .filter(|e| {
+                e.is_happening(now)
+                    || e.start
+                        .date_time
+                        .is_some_and(|s| s > now && s.with_timezone(&tz).date_naive() == today)
+            })
+            .collect()
+    }
 
-        format!("{:02}:{:02}:{:02}", hours, minutes, remaining_seconds)
+    // The first event that starts once the room frees up
+    pub fn next_after_busy(&self, now: DateTime<Utc>) -> Option<&Event> {
+        let free_at = self.busy_until(now).unwrap_or(now);
+        self.events
+            .iter()
+            .find(|e| e.start.date_time.is_some_and(|s| s >= free_at && s > now))
     }
 }
 
@@ -77,42 +178,67 @@ impl Event {
 pub struct CalendarEvents {
     pub kind: String,
     pub items: Vec<Event>,
+    // Only there when there were more events than fit on the first page
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+    // What's moved or been called off lately, for the signs to mention
+    #[serde(skip)]
+    pub changes: Vec<Change>,
 }
 
 impl CalendarEvents {
     // Call the Google Calendar API and return a usable object from that
-    pub async fn new() -> anyhow::Result<Self> {
-        let gcal_resp = Self::query_gcal().await?;
+    pub async fn new(now: DateTime<Utc>) -> anyhow::Result<Self> {
+        let gcal_resp = Self::query_gcal(now).await?;
         let events: Self = serde_json::from_str::<CalendarEvents>(gcal_resp.as_str())
             .map_err(|e| anyhow!("{}", e))?;
         Ok(events)
     }
 
-    // Update the contents of this struct
-    pub async fn update(&mut self) -> anyhow::Result<()> {
-        let gcal_resp = Self::query_gcal().await?;
-        self.items = serde_json::from_str::<CalendarEvents>(gcal_resp.as_str())
-            .map_err(|e| anyhow!("{}", e))?
-            .items;
+    // Update the contents of this struct, noting anything starting within
+    // `window` that's changed since last time
+    pub async fn update(&mut self, now: DateTime<Utc>, window: Duration) -> anyhow::Result<()> {
+        let gcal_resp = Self::query_gcal(now).await?;
+        let fetched = serde_json::from_str::<CalendarEvents>(gcal_resp.as_str())
+            .map_err(|e| anyhow!("{}", e))?;
+        let (items, truncated) = (fetched.items, fetched.next_page_token.is_some());
+        self.changes.retain(|c| c.is_live(now));
+        for change in change::detect(&self.items, &items, truncated, now, window) {
+            // Only the latest word on an event, for each room
+            self.changes.retain(|c| {
+                c.event.id != change.event.id || c.event.location != change.event.location
+            });
+            self.changes.push(change);
+        }
+        self.items = items;
         Ok(())
     }
 
+    // The notices for a sign at this location
+    pub fn changes_at_location(&self, location: &str) -> Vec<&Change> {
+        self.changes
+            .iter()
+            .filter(|c| c.reaches(location))
+            .collect()
+    }
+
     // Perform Google Calendar API Call
-    async fn query_gcal() -> anyhow::Result<String> {
-        let utc: DateTime<Utc> = Utc::now();
-        let iso_time = utc.to_rfc3339();
+    async fn query_gcal(now: DateTime<Utc>) -> anyhow::Result<String> {
+        // Hang on to events that ended a little while ago, so signs can
+        // say that they just did
+        let iso_time = (now - Duration::try_minutes(RECENTLY_ENDED).unwrap()).to_rfc3339();
         let api_key = env::var("API_KEY")?;
         let calendar_id = env::var("CALENDAR_ID")?;
 
         let params = [
-            ("maxResults", "10"),
+            ("maxResults", "50"),
             ("orderBy", "startTime"),
             ("showDeleted", "false"),
             ("singleEvents", "true"),
             ("timeMin", &iso_time),
             (
                 "fields",
-                "kind,items(location, start, end, summary, description)",
+                "kind,nextPageToken,items(id, location, start, end, summary, description, extendedProperties, visibility, organizer)",
             ),
             ("key", &api_key),
         ];
@@ -132,15 +258,33 @@ impl CalendarEvents {
         Ok(body)
     }
 
-    pub fn get_next_at_location(&self, location: &str) -> Option<Event> {
-        self.items
+    // The first event at a location that isn't over yet. The calendar
+    // hands back ones that ended a little while ago too.
+    pub fn get_next_at_location(&self, location: &str, now: DateTime<Utc>) -> Option<Event> {
+        self.timeline_at_location(location)
+            .events
+            .into_iter()
+            .find(|e| e.end.date_time.is_none_or(|end| end > now))
+    }
+
+    // Everything coming up at a location, in order
+    pub fn timeline_at_location(&self, location: &str) -> Timeline {
+        let mut events: Vec<Event> = self
+            .items
             .iter()
-            .find(|e| {
+            .filter(|e| {
                 e.location.as_ref().is_some_and(|l| l.contains(location))
                     && !e.summary.contains("CANCELED")
                     && !e.summary.contains("CANCELLED")
+                    && !e.sign().hide
             })
             .cloned()
+            .collect();
+        // Google already sorts by start time, but we don't want to lean on that.
+        // All-day events have no start time, and None sorts before any time,
+        // so they end up at the front.
+        events.sort_by_key(|e| e.start.date_time);
+        Timeline { events }
     }
 
     // Check if the provided range of DateTimes overlap with anything
@@ -173,17 +317,89 @@ impl CalendarEvents {
     }
 }
 
+// Events for the tests to work with: an event in the Lounge, to be dressed
+// up with the `with_` methods as a test needs
+#[cfg(test)]
+pub fn event(summary: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Event {
+    let time = |t| EventTimeInfo {
+        date_time: Some(t),
+        date: None,
+        time_zone: None,
+    };
+    Event {
+        id: None,
+        summary: summary.to_string(),
+        description: None,
+        location: Some("Lounge".to_string()),
+        start: time(start),
+        end: time(end),
+        extended_properties: None,
+        visibility: None,
+        organizer: None,
+    }
+}
+
+// The day the tests' events are on, unless they need some other day
+#[cfg(test)]
+pub fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+    use chrono::TimeZone;
+    Utc.with_ymd_and_hms(2020, 1, 1, hour, minute, 0).unwrap()
+}
+
+#[cfg(test)]
+impl Event {
+    pub fn with_id(self, id: &str) -> Self {
+        Event {
+            id: Some(id.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_location(self, location: &str) -> Self {
+        Event {
+            location: Some(location.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_description(self, description: &str) -> Self {
+        Event {
+            description: Some(description.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_visibility(self, visibility: &str) -> Self {
+        Event {
+            visibility: Some(visibility.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_organizer(self, email: &str) -> Self {
+        Event {
+            organizer: Some(Organizer {
+                email: Some(email.to_string()),
+            }),
+            ..self
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::calendar::{CalendarEvents, Event, EventTimeInfo};
+    use crate::calendar::{at, event, CalendarEvents, Event, EventTimeInfo};
     use chrono::prelude::*;
 
     #[test]
     fn test_get_next_at_location() {
         let events = CalendarEvents {
             kind: "".to_string(),
+            next_page_token: None,
+            changes: vec![],
             items: vec![
                 Event {
+                    id: None,
                     summary: "Test".to_string(),
                     description: None,
                     location: Some("Lounge".to_string()),
@@ -197,8 +413,12 @@ mod tests {
                         date: None,
                         time_zone: None,
                     },
+                    extended_properties: None,
+                    visibility: None,
+                    organizer: None,
                 },
                 Event {
+                    id: None,
                     summary: "Test Number 2".to_string(),
                     description: None,
                     location: Some("Lounge".to_string()),
@@ -212,23 +432,36 @@ mod tests {
                         date: None,
                         time_zone: None,
                     },
+                    extended_properties: None,
+                    visibility: None,
+                    organizer: None,
                 },
             ],
         };
 
         // The next event should be "Test"
+        let now = Utc.with_ymd_and_hms(2019, 12, 31, 23, 0, 0).unwrap();
         assert_eq!(
-            events.get_next_at_location("Lounge").unwrap().summary,
+            events.get_next_at_location("Lounge", now).unwrap().summary,
             "Test"
         );
+        // Until it's over, even though the calendar still has it
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 1, 10, 0).unwrap();
+        assert_eq!(
+            events.get_next_at_location("Lounge", now).unwrap().summary,
+            "Test Number 2"
+        );
     }
 
     #[test]
     fn test_is_free_at_location() {
         let events = CalendarEvents {
             kind: "".to_string(),
+            next_page_token: None,
+            changes: vec![],
             items: vec![
                 Event {
+                    id: None,
                     summary: "Test".to_string(),
                     description: None,
                     location: Some("Lounge".to_string()),
@@ -242,8 +475,12 @@ mod tests {
                         date: None,
                         time_zone: None,
                     },
+                    extended_properties: None,
+                    visibility: None,
+                    organizer: None,
                 },
                 Event {
+                    id: None,
                     summary: "Test Number 2".to_string(),
                     description: None,
                     location: Some("Lounge".to_string()),
@@ -257,6 +494,9 @@ mod tests {
                         date: None,
                         time_zone: None,
                     },
+                    extended_properties: None,
+                    visibility: None,
+                    organizer: None,
                 },
             ],
         };
@@ -271,4 +511,47 @@ mod tests {
         query_end = Utc.with_ymd_and_hms(2020, 2, 1, 1, 30, 0).unwrap();
         assert!(events.is_free_at_location("Lounge", query_start, query_end));
     }
+
+    #[test]
+    fn test_timeline_at_location() {
+        let events = CalendarEvents {
+            kind: "".to_string(),
+            next_page_token: None,
+            changes: vec![],
+            items: vec![
+                event("Late", at(22, 0), at(23, 0)),
+                event("First", at(18, 0), at(19, 0)),
+                event("Second", at(19, 0), at(20, 30)),
+                event("Overlapping", at(20, 0), at(21, 0)),
+                event("CANCELLED Karaoke", at(21, 0), at(22, 0)),
+                event("Study Group", at(17, 0), at(18, 0)).with_description("sign-hide"),
+            ],
+        };
+        let timeline = events.timeline_at_location("Lounge");
+
+        // Sorted, with the cancelled and hidden events gone
+        let summaries: Vec<&str> = timeline.events.iter().map(|e| e.summary.as_str()).collect();
+        assert_eq!(summaries, vec!["First", "Second", "Overlapping", "Late"]);
+
+        // Before anything has started, the room is free
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 17, 0, 0).unwrap();
+        assert!(timeline.current(now).is_none());
+        assert!(timeline.busy_until(now).is_none());
+        assert_eq!(timeline.next(now).unwrap().summary, "First");
+        assert_eq!(timeline.next_after_busy(now).unwrap().summary, "First");
+
+        // Back-to-back and overlapping events run together
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 30, 0).unwrap();
+        assert_eq!(timeline.current(now).unwrap().summary, "First");
+        assert_eq!(
+            timeline.busy_until(now).unwrap(),
+            Utc.with_ymd_and_hms(2020, 1, 1, 21, 0, 0).unwrap()
+        );
+        assert_eq!(timeline.next(now).unwrap().summary, "Second");
+        assert_eq!(timeline.next_after_busy(now).unwrap().summary, "Late");
+
+        // An event is over at its end time
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 21, 0, 0).unwrap();
+        assert!(timeline.current(now).is_none());
+    }
 }
@@ -0,0 +1,273 @@
+// Events that move, get rescheduled, or get called off shortly before they
+// start. Every refresh of the calendar is held up against the last one, by
+// event ID, and anything that changed close enough to its start gets a
+// notice on the sign in the room it was supposed to be in, like "Board
+// Games moved to Library" or "Movie Night CANCELLED". People who didn't get
+// the message still turn up at the old room, so that's where it goes.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::calendar::{Event, Timeline};
+use crate::config::LocationConfig;
+use crate::locale::Catalog;
+use crate::power::Power;
+use crate::privacy;
+use crate::render::{self, Geometry, Screen};
+
+// How long before an event starts a change to it gets a notice, unless
+// CHANGE_WINDOW says otherwise
+pub const DEFAULT_WINDOW_MINUTES: i64 = 120;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    // To another room
+    Moved(String),
+    // To another time
+    Rescheduled(DateTime<Utc>),
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    // The event as it was before it changed
+    pub event: Event,
+    pub kind: ChangeKind,
+    // When to stop saying so: once the event would have been over
+    pub until: DateTime<Utc>,
+}
+
+impl Change {
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        now < self.until
+    }
+
+    // Whether it's news for the sign at this location
+    pub fn reaches(&self, location: &str) -> bool {
+        self.event
+            .location
+            .as_ref()
+            .is_some_and(|l| l.contains(location))
+    }
+
+    // Board Games moved to Library
+    fn text(&self, now: DateTime<Utc>, location: &LocationConfig) -> String {
+        // Private events keep their titles to themselves here too
+        let timeline = Timeline {
+            events: vec![self.event.clone()],
+        };
+        let title = privacy::redact(&timeline, location).events[0].title();
+        let catalog = location.locale.catalog();
+        match &self.kind {
+            ChangeKind::Moved(to) => Catalog::fill2(catalog.moved, &title, to),
+            ChangeKind::Rescheduled(start) => {
+                let local = start.with_timezone(&location.timezone);
+                let today = now.with_timezone(&location.timezone).date_naive();
+                let at = match local.date_naive() == today {
+                    true => location.clock.time(&local),
+                    false => location.locale.weekday_time(&local, location.clock),
+                };
+                Catalog::fill2(catalog.rescheduled, &title, &at)
+            }
+            ChangeKind::Cancelled => Catalog::fill(catalog.cancelled, &title),
+        }
+    }
+}
+
+fn is_cancelled(event: &Event) -> bool {
+    event.summary.contains("CANCELED") || event.summary.contains("CANCELLED")
+}
+
+// What happened between two snapshots of the calendar to the events that
+// were starting within `window`. Events that vanish are taken as called
+// off, unless `after` was `truncated` and they'd have come after the last
+// event on it, where they could just have been pushed onto the next page.
+// Events hidden from the signs stay hidden here too.
+pub fn detect(
+    before: &[Event],
+    after: &[Event],
+    truncated: bool,
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Vec<Change> {
+    let last = after.iter().filter_map(|a| a.start.date_time).max();
+    let vanished = |start: DateTime<Utc>| !truncated || last.is_some_and(|last| start <= last);
+    before
+        .iter()
+        .filter(|b| !is_cancelled(b))
+        .filter(|b| !b.sign().hide)
+        .filter_map(|b| {
+            let id = b.id.as_ref()?;
+            let start = b.start.date_time?;
+            if start <= now || start - now > window {
+                return None;
+            }
+            let kind = match after.iter().find(|a| a.id.as_ref() == Some(id)) {
+                None if vanished(start) => ChangeKind::Cancelled,
+                None => return None,
+                Some(a) if is_cancelled(a) => ChangeKind::Cancelled,
+                Some(a) if a.location != b.location => ChangeKind::Moved(a.location.clone()?),
+                Some(a) if a.start.date_time != b.start.date_time => {
+                    ChangeKind::Rescheduled(a.start.date_time?)
+                }
+                Some(_) => return None,
+            };
+            Some(Change {
+                event: b.clone(),
+                kind,
+                until: b.end.date_time.unwrap_or(start),
+            })
+        })
+        .collect()
+}
+
+// Put the notices for a sign on its screen. A room that's free has nothing
+// better to show, so they take the whole screen. One that's in use keeps
+// its event, and gets them along the last row.
+pub fn apply(
+    screen: &mut Screen,
+    changes: &[&Change],
+    busy: bool,
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    geometry: Geometry,
+) {
+    let live: Vec<&Change> = changes.iter().filter(|c| c.is_live(now)).cloned().collect();
+    let texts: Vec<String> = live.iter().map(|c| c.text(now, location)).collect();
+    if texts.is_empty() {
+        return;
+    }
+    for c in &live {
+        screen.refresh_at(c.until, now);
+    }
+
+    if busy {
+        screen
+            .rows
+            .resize(geometry.content_rows() - 1, String::new());
+        screen.rows.push(texts.join(" / "));
+        return;
+    }
+    let paged = render::paged(texts, geometry.content_rows(), now, location.page_seconds);
+    screen.rows = paged.rows;
+    screen.page = paged.page;
+    screen.label = None;
+    if let Some(refresh) = paged.refresh {
+        screen.refresh_at(now + refresh, now);
+    }
+    screen.power = Power::On;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{at, event, Event};
+    use crate::change::{apply, detect, ChangeKind};
+    use crate::config::LocationConfig;
+    use crate::render::{Geometry, Screen};
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use chrono_tz::UTC;
+
+    fn booked(id: &str, summary: &str, location: &str, start: u32) -> Event {
+        event(summary, at(start, 0), at(start + 1, 0))
+            .with_id(id)
+            .with_location(location)
+    }
+
+    fn hidden(id: &str, summary: &str, location: &str, start: u32) -> Event {
+        booked(id, summary, location, start).with_description("sign-hide")
+    }
+
+    #[test]
+    fn test_detect() {
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 0, 0).unwrap();
+        let window = Duration::hours(2);
+        let before = [
+            booked("games", "Board Games", "Lounge", 19),
+            booked("movie", "Movie Night", "Lounge", 20),
+            booked("smash", "Smash", "Lounge", 19),
+            booked("talk", "Tech Talk", "Lounge", 19),
+            booked("later", "Late Night", "Lounge", 22),
+            hidden("party", "Surprise Party", "Lounge", 19),
+        ];
+        let after = [
+            booked("games", "Board Games", "Library", 19),
+            booked("smash", "Smash", "Lounge", 21),
+            booked("talk", "Tech Talk", "Lounge", 19),
+            booked("later", "Late Night", "Library", 22),
+            hidden("party", "Surprise Party", "Library", 19),
+        ];
+
+        // Too far off to matter yet, unchanged, and hidden from the signs,
+        // get nothing
+        let changes = detect(&before, &after, false, now, window);
+        let kinds: Vec<(&str, &ChangeKind)> = changes
+            .iter()
+            .map(|c| (c.event.summary.as_str(), &c.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("Board Games", &ChangeKind::Moved("Library".to_string())),
+                ("Movie Night", &ChangeKind::Cancelled),
+                (
+                    "Smash",
+                    &ChangeKind::Rescheduled(Utc.with_ymd_and_hms(2020, 1, 1, 21, 0, 0).unwrap())
+                ),
+            ]
+        );
+
+        // The old room's sign says so, until the event would have ended
+        let location = LocationConfig {
+            timezone: UTC,
+            ..Default::default()
+        };
+        let lounge: Vec<_> = changes.iter().filter(|c| c.reaches("Lounge")).collect();
+        // Off the end of a page isn't off the calendar, but anything that
+        // would have come before the end of it is
+        let page = [booked("games", "Board Games", "Library", 19)];
+        let paged = detect(&before, &page, true, now, window);
+        let kinds: Vec<&str> = paged.iter().map(|c| c.event.summary.as_str()).collect();
+        assert_eq!(kinds, ["Board Games", "Smash", "Tech Talk"]);
+        let missed = detect(&before, &page[..0], true, now, window);
+        assert!(missed.is_empty());
+
+        let mut screen = Screen::from_rows(vec!["No upcoming events.".to_string()]);
+        apply(
+            &mut screen,
+            &lounge,
+            false,
+            now,
+            &location,
+            Geometry::new(4, 40).unwrap(),
+        );
+        assert_eq!(
+            screen.rows,
+            [
+                "Board Games moved to Library",
+                "Movie Night CANCELLED",
+                "Smash now at 21:00"
+            ]
+        );
+        let mut screen = Screen::from_rows(vec!["Now: Smash".to_string(), "1h left".to_string()]);
+        let later = now + Duration::minutes(90);
+        let two_rows = Geometry::new(2, 40).unwrap();
+        apply(&mut screen, &lounge, true, later, &location, two_rows);
+        assert_eq!(
+            screen.rows,
+            [
+                "Now: Smash",
+                "Board Games moved to Library / Movie Night CANCELLED / Smash now at 21:00"
+            ]
+        );
+        let mut screen = Screen::from_rows(vec![]);
+        apply(
+            &mut screen,
+            &lounge,
+            false,
+            now + Duration::hours(4),
+            &location,
+            Geometry::default(),
+        );
+        assert!(screen.rows.is_empty());
+    }
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+
+// Where "now" comes from. Everything that cares about the time gets it
+// from one of these (or is handed a time that came from one), so that
+// screens can be drawn for any moment, not just this one.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+// The actual time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// A moment that never moves, for previews and tests
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
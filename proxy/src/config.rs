@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+};
+
+use crate::countdown::CountdownConfig;
+use crate::locale::{ClockFormat, Locale};
+use crate::playlist::Slot;
+use crate::power::PowerConfig;
+use crate::privacy::PrivacyConfig;
+use crate::render::Layout;
+use crate::source::SourceConfig;
+use crate::urgency::UrgencyConfig;
+use crate::webhook::WebhookConfig;
+
+// Settings for a single location. Anything left out of the config file
+// falls back to the defaults below.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LocationConfig {
+    pub timezone: Tz,
+    pub locale: Locale,
+    pub clock: ClockFormat,
+    pub countdown: CountdownConfig,
+    pub layout: Layout,
+    // How long each page stays up on layouts that page
+    pub page_seconds: i64,
+    pub urgency: UrgencyConfig,
+    // When to turn the backlight or the whole display off
+    pub power: PowerConfig,
+    // Which events keep their titles to themselves
+    pub privacy: PrivacyConfig,
+    // What the sign takes turns showing. Empty for just the layout.
+    pub playlist: Vec<Slot>,
+    // A Rhai script with rules of the location's own for its events
+    pub script: Option<String>,
+    // Where to POST when the room's state changes
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Default for LocationConfig {
+    fn default() -> Self {
+        LocationConfig {
+            timezone: chrono_tz::US::Eastern,
+            locale: Locale::default(),
+            clock: ClockFormat::default(),
+            countdown: CountdownConfig::default(),
+            layout: Layout::default(),
+            page_seconds: 10,
+            urgency: UrgencyConfig::default(),
+            power: PowerConfig::default(),
+            privacy: PrivacyConfig::default(),
+            playlist: vec![],
+            script: None,
+            webhooks: vec![],
+        }
+    }
+}
+
+// A set of locations that one sign covers, like a hallway sign between
+// a few rooms. The rest of the settings are for the sign itself.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GroupConfig {
+    pub locations: Vec<String>,
+    #[serde(flatten)]
+    pub display: LocationConfig,
+}
+
+// Everything we know about the signs out in the house, loaded from the
+// file at LOCATION_CONFIG.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Config {
+    pub locations: HashMap<String, LocationConfig>,
+    pub groups: HashMap<String, GroupConfig>,
+    // Feeds and files for playlists to show, by name
+    pub sources: HashMap<String, SourceConfig>,
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        let path = env::var("LOCATION_CONFIG").unwrap_or("locations.json".to_string());
+        match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str::<Config>(&s).map_err(|e| anyhow!("{}: {}", path, e)),
+            Err(_) => {
+                println!("No location config at {}, using defaults", path);
+                Ok(Config::default())
+            }
+        }
+    }
+
+    // Location names come in from the route in all sorts of cases, so
+    // match them loosely.
+    pub fn location(&self, location: &str) -> LocationConfig {
+        self.locations
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(location))
+            .map(|(_, c)| c.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn group(&self, group: &str) -> Option<GroupConfig> {
+        self.groups
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(group))
+            .map(|(_, c)| c.clone())
+    }
+
+    // The sources some playlist actually shows. Nothing else is worth
+    // fetching.
+    pub fn sources_in_use(&self) -> HashMap<String, SourceConfig> {
+        let shown: HashSet<&String> = self
+            .locations
+            .values()
+            .chain(self.groups.values().map(|g| &g.display))
+            .flat_map(|l| &l.playlist)
+            .filter_map(|slot| slot.source.as_ref())
+            .collect();
+        self.sources
+            .iter()
+            .filter(|(name, _)| shown.contains(name))
+            .map(|(name, c)| (name.clone(), c.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::locale::Locale;
+    use crate::playlist::Condition;
+    use crate::render::Layout;
+
+    #[test]
+    fn test_sample_config() {
+        let config: Config =
+            serde_json::from_str(include_str!("../locations.json.sample")).unwrap();
+
+        assert_eq!(config.location("lounge").layout, Layout::NowNext);
+        assert_eq!(config.location("lounge").power.closed[0].days.len(), 5);
+        assert_eq!(config.location("lounge").playlist[1].when, Condition::Free);
+        assert_eq!(config.location("lounge").webhooks[0].on.len(), 2);
+        assert_eq!(config.location("LIBRARY").locale, Locale::Es);
+        // Anything else gets the defaults
+        assert_eq!(config.location("Attic").layout, Layout::Countdown);
+
+        let hallway = config.group("hallway").unwrap();
+        assert_eq!(hallway.locations.len(), 3);
+        assert_eq!(hallway.display.page_seconds, 5);
+        assert_eq!(config.sources.len(), 2);
+        // Nothing's showing the messages
+        let in_use: Vec<String> = config.sources_in_use().into_keys().collect();
+        assert_eq!(in_use, ["house-news"]);
+    }
+}
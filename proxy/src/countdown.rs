@@ -0,0 +1,300 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+use crate::config::LocationConfig;
+use crate::locale::{Catalog, Unit};
+
+// How a duration is spelled out on the sign
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationStyle {
+    // 02:15:00, rolling over into days (3d 02:15:00)
+    #[default]
+    Clock,
+    // 2h 15m
+    Compact,
+    // In 3 days, Tomorrow 7pm
+    Relative,
+}
+
+// Per-location settings for countdowns
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CountdownConfig {
+    pub style: DurationStyle,
+    // Once an event is further out than this, show when it starts instead
+    // of how long until it starts.
+    pub absolute_after_hours: Option<i64>,
+    // For the compact and relative styles, only show seconds once there's
+    // less than a minute left. The device polls every HZ ms anyway, so a
+    // ticking countdown is mostly a lie. The clock style has seconds the
+    // whole way down regardless, since that's what it looks like.
+    pub precise_last_minute: bool,
+}
+
+impl Default for CountdownConfig {
+    fn default() -> Self {
+        CountdownConfig {
+            style: DurationStyle::Clock,
+            absolute_after_hours: None,
+            precise_last_minute: true,
+        }
+    }
+}
+
+// Text for an event that starts `until` from now, at `start`
+pub fn starts_in(until: Duration, start: DateTime<Utc>, location: &LocationConfig) -> String {
+    let config = &location.countdown;
+    let catalog = location.locale.catalog();
+
+    if let Some(hours) = config.absolute_after_hours {
+        if until > Duration::hours(hours) {
+            let local_start = start.with_timezone(&location.timezone);
+            // Past a week out the weekday alone is ambiguous
+            return match until > Duration::days(6) {
+                true => location.locale.date_time(&local_start, location.clock),
+                false => location.locale.weekday_time(&local_start, location.clock),
+            };
+        }
+    }
+
+    match config.style {
+        DurationStyle::Relative => relative(until, start, location),
+        style => Catalog::fill(catalog.starts_in, &format_duration(until, style, location)),
+    }
+}
+
+// Text for an event that ends `left` from now
+pub fn time_left(left: Duration, location: &LocationConfig) -> String {
+    let style = match location.countdown.style {
+        // "Ends in 2 hours" doesn't fit next to a title, so fall back to
+        // the compact form.
+        DurationStyle::Relative => DurationStyle::Compact,
+        style => style,
+    };
+    Catalog::fill(
+        location.locale.catalog().left,
+        &format_duration(left, style, location),
+    )
+}
+
+pub fn format_duration(
+    duration: Duration,
+    style: DurationStyle,
+    location: &LocationConfig,
+) -> String {
+    let seconds = duration.num_seconds().max(0);
+    let days = seconds / 86400;
+    let hours = (seconds % 86400) / 3600;
+    let minutes = (seconds % 3600) / 60;
+    let remaining_seconds = seconds % 60;
+    let [d, h, m, s] = location.locale.catalog().abbreviations;
+
+    match style {
+        DurationStyle::Clock => {
+            if days > 0 {
+                format!(
+                    "{}{} {:02}:{:02}:{:02}",
+                    days, d, hours, minutes, remaining_seconds
+                )
+            } else {
+                format!("{:02}:{:02}:{:02}", hours, minutes, remaining_seconds)
+            }
+        }
+        DurationStyle::Compact | DurationStyle::Relative => {
+            if days > 0 {
+                format!("{}{} {}{}", days, d, hours, h)
+            } else if hours > 0 {
+                format!("{}{} {}{}", hours, h, minutes, m)
+            } else if minutes > 0 || !location.countdown.precise_last_minute {
+                // Round up so that "0m" is never shown
+                format!("{}{}", minutes.max(1), m)
+            } else {
+                format!("{}{}", remaining_seconds, s)
+            }
+        }
+    }
+}
+
+fn relative(until: Duration, start: DateTime<Utc>, location: &LocationConfig) -> String {
+    let catalog = location.locale.catalog();
+    let local_start = start.with_timezone(&location.timezone);
+    let local_now = (start - until).with_timezone(&location.timezone);
+    let days = (local_start.date_naive() - local_now.date_naive()).num_days();
+
+    match days {
+        0 if until < Duration::hours(1) => Catalog::fill(
+            catalog.starts_in,
+            &format_duration(until, DurationStyle::Compact, location),
+        ),
+        0 => Catalog::fill(
+            catalog.starts_in,
+            &location.locale.count(until.num_hours(), Unit::Hour),
+        ),
+        1 => Catalog::fill(catalog.tomorrow, &location.clock.hour(&local_start)),
+        d => Catalog::fill(catalog.starts_in, &location.locale.count(d, Unit::Day)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::LocationConfig;
+    use crate::countdown::{format_duration, starts_in, time_left, CountdownConfig, DurationStyle};
+    use crate::locale::{ClockFormat, Locale};
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use chrono_tz::US::Eastern;
+
+    fn config(style: DurationStyle) -> LocationConfig {
+        LocationConfig {
+            countdown: CountdownConfig {
+                style,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_clock_rolls_over_days() {
+        let c = config(DurationStyle::Clock);
+        assert_eq!(
+            format_duration(Duration::seconds(86399), c.countdown.style, &c),
+            "23:59:59"
+        );
+        assert_eq!(
+            format_duration(Duration::hours(24), c.countdown.style, &c),
+            "1d 00:00:00"
+        );
+        assert_eq!(
+            format_duration(Duration::hours(72), c.countdown.style, &c),
+            "3d 00:00:00"
+        );
+    }
+
+    #[test]
+    fn test_compact_boundaries() {
+        let c = config(DurationStyle::Compact);
+        assert_eq!(
+            format_duration(Duration::seconds(59), c.countdown.style, &c),
+            "59s"
+        );
+        assert_eq!(
+            format_duration(Duration::seconds(60), c.countdown.style, &c),
+            "1m"
+        );
+        assert_eq!(
+            format_duration(Duration::seconds(3599), c.countdown.style, &c),
+            "59m"
+        );
+        assert_eq!(
+            format_duration(Duration::seconds(3600), c.countdown.style, &c),
+            "1h 0m"
+        );
+        assert_eq!(
+            format_duration(Duration::minutes(135), c.countdown.style, &c),
+            "2h 15m"
+        );
+        assert_eq!(
+            format_duration(Duration::hours(24), c.countdown.style, &c),
+            "1d 0h"
+        );
+
+        // Without the last minute being precise, never show seconds or 0m
+        let mut c = c;
+        c.countdown.precise_last_minute = false;
+        assert_eq!(
+            format_duration(Duration::seconds(59), c.countdown.style, &c),
+            "1m"
+        );
+        assert_eq!(
+            format_duration(Duration::seconds(1), c.countdown.style, &c),
+            "1m"
+        );
+        // which the clock style doesn't go by
+        assert_eq!(
+            format_duration(Duration::seconds(59), DurationStyle::Clock, &c),
+            "00:00:59"
+        );
+    }
+
+    #[test]
+    fn test_relative_boundaries() {
+        let c = LocationConfig {
+            clock: ClockFormat::H12,
+            ..config(DurationStyle::Relative)
+        };
+        // 7pm Eastern on a Friday
+        let start = Eastern
+            .with_ymd_and_hms(2024, 3, 1, 19, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(starts_in(Duration::seconds(30), start, &c), "In 30s");
+        assert_eq!(starts_in(Duration::minutes(59), start, &c), "In 59m");
+        assert_eq!(starts_in(Duration::minutes(60), start, &c), "In 1 hour");
+        assert_eq!(starts_in(Duration::minutes(150), start, &c), "In 2 hours");
+
+        // Midnight local time is what makes it tomorrow, not 24 hours
+        assert_eq!(starts_in(Duration::hours(19), start, &c), "In 19 hours");
+        assert_eq!(starts_in(Duration::hours(20), start, &c), "Tomorrow 7pm");
+        assert_eq!(starts_in(Duration::hours(43), start, &c), "Tomorrow 7pm");
+        assert_eq!(starts_in(Duration::hours(44), start, &c), "In 2 days");
+
+        let half_past = start + Duration::minutes(30);
+        assert_eq!(
+            starts_in(Duration::hours(24), half_past, &c),
+            "Tomorrow 7:30pm"
+        );
+
+        let c = LocationConfig {
+            locale: Locale::Es,
+            ..config(DurationStyle::Relative)
+        };
+        assert_eq!(starts_in(Duration::hours(20), start, &c), "Mañana 19:00");
+        assert_eq!(starts_in(Duration::hours(44), start, &c), "En 2 días");
+    }
+
+    #[test]
+    fn test_absolute_threshold() {
+        let mut c = config(DurationStyle::Compact);
+        c.countdown.absolute_after_hours = Some(24);
+        let start = Eastern
+            .with_ymd_and_hms(2024, 3, 1, 19, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(starts_in(Duration::hours(24), start, &c), "In 1d 0h");
+        assert_eq!(
+            starts_in(Duration::hours(24) + Duration::seconds(1), start, &c),
+            "Fri 19:00"
+        );
+
+        // A week out, show the date as well
+        assert_eq!(starts_in(Duration::days(7), start, &c), "Mar 1 19:00");
+        c.locale = Locale::De;
+        assert_eq!(starts_in(Duration::days(7), start, &c), "1 Mär 19:00");
+    }
+
+    #[test]
+    fn test_time_left() {
+        assert_eq!(
+            time_left(Duration::minutes(12), &config(DurationStyle::Clock)),
+            "00:12:00 Left"
+        );
+        assert_eq!(
+            time_left(Duration::minutes(12), &config(DurationStyle::Relative)),
+            "12m Left"
+        );
+        assert_eq!(
+            time_left(Duration::seconds(5), &config(DurationStyle::Compact)),
+            "5s Left"
+        );
+
+        let c = LocationConfig {
+            locale: Locale::De,
+            ..config(DurationStyle::Clock)
+        };
+        assert_eq!(time_left(Duration::hours(25), &c), "Noch 1T 01:00:00");
+    }
+}
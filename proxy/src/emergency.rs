@@ -0,0 +1,160 @@
+// Emergency broadcasts, for when every sign in the house needs to say the
+// same thing right now. There's at most one going at a time, it goes to
+// every sign, and it stays up until an admin clears it. Devices poll for it
+// on a much shorter interval than they do for events, and drop whatever
+// they're scrolling to show it.
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use display_core::charset;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+use crate::announcement::DbPool;
+use crate::power::Power;
+use crate::render::{Geometry, Screen};
+use crate::schema::broadcasts;
+
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable, Serialize)]
+#[diesel(table_name = broadcasts)]
+pub struct Broadcast {
+    // 0 when there's no database to keep it in
+    pub id: i32,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+    pub cleared_at: Option<DateTime<Utc>>,
+}
+
+// What gets posted to the API
+#[derive(Debug, Deserialize)]
+pub struct BroadcastRequest {
+    pub message: String,
+}
+
+// Take over a sign's screen with the broadcast
+pub fn apply(screen: &mut Screen, broadcast: &Broadcast) {
+    screen.rows = broadcast.message.lines().map(String::from).collect();
+    screen.page = None;
+    screen.label = None;
+    screen.flash = true;
+    screen.power = Power::On;
+    screen.emergency = true;
+    // Nothing changes until it's cleared, and the fast poll will see that
+    screen.refresh = None;
+}
+
+// Just the message, over the whole panel, for the fast poll
+pub fn to_text(broadcast: &Broadcast, geometry: Geometry) -> String {
+    let rows: Vec<&str> = broadcast.message.lines().take(geometry.rows).collect();
+    charset::lcd_safe(&rows.join("\n"))
+}
+
+// Broadcasts are kept in memory so polling for them is cheap, and in the
+// database, when there is one, so they outlast a restart
+#[derive(Default)]
+pub struct Broadcasts {
+    pool: Option<DbPool>,
+    current: RwLock<Option<Broadcast>>,
+}
+
+impl Broadcasts {
+    // Pick up the one that hasn't been cleared, if there is one
+    pub fn load(pool: Option<DbPool>) -> anyhow::Result<Self> {
+        let current = match &pool {
+            Some(pool) => broadcasts::table
+                .filter(broadcasts::cleared_at.is_null())
+                .order(broadcasts::created_at.desc())
+                .select(Broadcast::as_select())
+                .first(&mut pool.get()?)
+                .optional()?,
+            None => None,
+        };
+        Ok(Broadcasts {
+            pool,
+            current: RwLock::new(current),
+        })
+    }
+
+    pub fn current(&self) -> Option<Broadcast> {
+        self.current.read().unwrap().clone()
+    }
+
+    // Replaces whatever was already going
+    pub fn start(&self, message: String, now: DateTime<Utc>) -> anyhow::Result<Broadcast> {
+        let broadcast = match &self.pool {
+            Some(pool) => {
+                let mut conn = pool.get()?;
+                conn.transaction(|conn| {
+                    diesel::update(broadcasts::table.filter(broadcasts::cleared_at.is_null()))
+                        .set(broadcasts::cleared_at.eq(now))
+                        .execute(conn)?;
+                    diesel::insert_into(broadcasts::table)
+                        .values((
+                            broadcasts::message.eq(&message),
+                            broadcasts::created_at.eq(now),
+                        ))
+                        .returning(Broadcast::as_returning())
+                        .get_result(conn)
+                })?
+            }
+            None => Broadcast {
+                id: 0,
+                message,
+                created_at: now,
+                cleared_at: None,
+            },
+        };
+        *self.current.write().unwrap() = Some(broadcast.clone());
+        Ok(broadcast)
+    }
+
+    // Whether there was anything to clear
+    pub fn clear(&self, now: DateTime<Utc>) -> anyhow::Result<bool> {
+        if let Some(pool) = &self.pool {
+            diesel::update(broadcasts::table.filter(broadcasts::cleared_at.is_null()))
+                .set(broadcasts::cleared_at.eq(now))
+                .execute(&mut pool.get()?)?;
+        }
+        Ok(self.current.write().unwrap().take().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emergency::{apply, to_text, Broadcasts};
+    use crate::power::Power;
+    use crate::render::{Geometry, Screen};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_broadcast() {
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 19, 0, 0).unwrap();
+        let broadcasts = Broadcasts::default();
+        assert_eq!(broadcasts.current(), None);
+        let broadcast = broadcasts
+            .start("FIRE ALARM\nLeave by the\nnearest exit".to_string(), now)
+            .unwrap();
+        assert_eq!(broadcasts.current(), Some(broadcast.clone()));
+
+        let mut screen = Screen::from_rows(vec!["Smash".to_string()]);
+        screen.page = Some("1/2".to_string());
+        screen.refresh = Some(Duration::minutes(5));
+        screen.power = Power::Off;
+        apply(&mut screen, &broadcast);
+        assert_eq!(screen.rows, ["FIRE ALARM", "Leave by the", "nearest exit"]);
+        assert_eq!(screen.page, None);
+        assert_eq!(screen.refresh, None);
+        assert_eq!(screen.power, Power::On);
+        assert!(screen.flash && screen.emergency);
+
+        assert_eq!(
+            to_text(&broadcast, Geometry::new(2, 16).unwrap()),
+            "FIRE ALARM\nLeave by the"
+        );
+
+        assert!(broadcasts.clear(now).unwrap());
+        assert!(!broadcasts.clear(now).unwrap());
+        assert_eq!(broadcasts.current(), None);
+    }
+}
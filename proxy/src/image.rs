@@ -0,0 +1,161 @@
+// Draw a screen as a PNG, for things that take pictures rather than text,
+// like e-paper panels and chat embeds. It's the same text a sign gets,
+// drawn with the sign's own font and scaled up to fit.
+
+use display_core::{
+    font::{self, glyph, lit},
+    frame,
+};
+
+use crate::render::Geometry;
+
+// Big enough for any e-paper panel we'd buy, small enough to not be a
+// way to make us allocate gigabytes
+const MAX_SIZE: u32 = 4096;
+
+// Dots between characters, and around the edge
+const SPACING: usize = 1;
+const MARGIN: usize = 2;
+
+const PAPER: u8 = 0xFF;
+const INK: u8 = 0x00;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for Size {
+    // A 7.5" e-paper panel
+    fn default() -> Self {
+        Size {
+            width: 800,
+            height: 480,
+        }
+    }
+}
+
+impl Size {
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        match (1..=MAX_SIZE).contains(&width) && (1..=MAX_SIZE).contains(&height) {
+            true => Some(Size { width, height }),
+            false => None,
+        }
+    }
+}
+
+// Which dots of the whole panel are lit, margin and all
+struct Dots {
+    width: usize,
+    height: usize,
+    lit: Vec<bool>,
+}
+
+impl Dots {
+    fn new(text: &str, geometry: Geometry) -> Self {
+        let rows = frame::compose(&frame::lines(text), geometry.rows);
+        let cell_width = font::WIDTH + SPACING;
+        let cell_height = font::HEIGHT + SPACING;
+        let width = geometry.cols * cell_width - SPACING + MARGIN * 2;
+        let height = geometry.rows * cell_height - SPACING + MARGIN * 2;
+
+        let mut dots = vec![false; width * height];
+        for (r, row) in rows.iter().enumerate() {
+            for (c, &b) in row.iter().take(geometry.cols).enumerate() {
+                let g = glyph(b);
+                for y in 0..font::HEIGHT {
+                    for x in 0..font::WIDTH {
+                        let dx = MARGIN + c * cell_width + x;
+                        let dy = MARGIN + r * cell_height + y;
+                        dots[dy * width + dx] = lit(&g, x, y);
+                    }
+                }
+            }
+        }
+        Dots {
+            width,
+            height,
+            lit: dots,
+        }
+    }
+}
+
+// Black on white, as big as it'll go while keeping the dots square, and
+// centred in whatever's left over
+pub fn png(text: &str, geometry: Geometry, size: Size) -> anyhow::Result<Vec<u8>> {
+    let dots = Dots::new(text, geometry);
+    let (width, height) = (size.width as usize, size.height as usize);
+    let scale = f64::min(
+        width as f64 / dots.width as f64,
+        height as f64 / dots.height as f64,
+    );
+    let left = (width as f64 - dots.width as f64 * scale) / 2.0;
+    let top = (height as f64 - dots.height as f64 * scale) / 2.0;
+
+    let mut pixels = vec![PAPER; width * height];
+    for py in 0..height {
+        let dy = ((py as f64 + 0.5 - top) / scale).floor();
+        if dy < 0.0 || dy >= dots.height as f64 {
+            continue;
+        }
+        for px in 0..width {
+            let dx = ((px as f64 + 0.5 - left) / scale).floor();
+            if dx < 0.0 || dx >= dots.width as f64 {
+                continue;
+            }
+            if dots.lit[dy as usize * dots.width + dx as usize] {
+                pixels[py * width + px] = INK;
+            }
+        }
+    }
+
+    let mut out = vec![];
+    let mut encoder = png::Encoder::new(&mut out, size.width, size.height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::image::{png, Size};
+    use crate::render::Geometry;
+
+    fn decode(data: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let mut reader = png::Decoder::new(data).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        (info, pixels)
+    }
+
+    #[test]
+    fn test_png() {
+        // A 16x2 panel is 99x21 dots with the margin, so 198x42 fits it
+        // exactly at two pixels a dot
+        let geometry = Geometry::new(2, 16).unwrap();
+        let data = png("T", geometry, Size::new(198, 42).unwrap()).unwrap();
+        let (info, pixels) = decode(&data);
+        assert_eq!((info.width, info.height), (198, 42));
+
+        // The top bar of the T starts two dots in from the corner
+        let at = |x: usize, y: usize| pixels[y * 198 + x];
+        assert_eq!(at(3, 3), 0xFF);
+        assert_eq!(at(4, 4), 0x00);
+        assert_eq!(at(13, 5), 0x00);
+        assert_eq!(at(14, 4), 0xFF);
+        // and the stem comes down the middle
+        assert_eq!(at(8, 10), 0x00);
+        assert_eq!(at(6, 10), 0xFF);
+
+        // Letterboxed when the shape doesn't match
+        let data = png("", geometry, Size::new(198, 100).unwrap()).unwrap();
+        assert_eq!(decode(&data).0.height, 100);
+
+        assert_eq!(Size::new(0, 10), None);
+        assert_eq!(Size::new(10, 5000), None);
+    }
+}
@@ -0,0 +1,413 @@
+use chrono::{DateTime, Datelike, TimeZone, Timelike};
+use serde::Deserialize;
+
+// Languages the signs can speak. Everything in here goes through
+// charset::lcd_safe on its way out, so accents the HD44780 can't draw
+// are fine to write down properly.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    De,
+    Fr,
+    Pl,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum ClockFormat {
+    #[default]
+    #[serde(rename = "24h")]
+    H24,
+    #[serde(rename = "12h")]
+    H12,
+}
+
+// CLDR plural categories, minus the ones none of our locales use. Polish
+// "many" is folded into Other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Plural {
+    One,
+    Few,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Unit {
+    Day,
+    Hour,
+}
+
+// Everything a sign says, for one language. Templates use {} for the
+// value being filled in.
+pub struct Catalog {
+    pub starts_in: &'static str,
+    pub left: &'static str,
+    pub tomorrow: &'static str,
+    pub no_events: &'static str,
+    pub now: &'static str,
+    // Title, then time
+    pub next: &'static str,
+    pub busy_until: &'static str,
+    pub free: &'static str,
+    // Stand-ins for the titles of private events
+    pub reserved: &'static str,
+    pub busy: &'static str,
+    // Labels for urgency states. Kept short, since they share the bottom
+    // row with the clock.
+    pub starting_now: &'static str,
+    pub ending_soon: &'static str,
+    pub just_ended: &'static str,
+    // Abbreviations for days, hours, minutes and seconds in compact durations
+    pub abbreviations: [&'static str; 4],
+    // [One, Few, Other]
+    pub days: [&'static str; 3],
+    pub hours: [&'static str; 3],
+    // Monday first
+    pub weekdays: [&'static str; 7],
+    pub months: [&'static str; 12],
+    // "14 Mar" rather than "Mar 14"
+    pub day_first: bool,
+    // Notices for events that changed at the last minute. Title, then
+    // where or when it is now.
+    pub moved: &'static str,
+    pub rescheduled: &'static str,
+    pub cancelled: &'static str,
+}
+
+const EN: Catalog = Catalog {
+    starts_in: "In {}",
+    left: "{} Left",
+    tomorrow: "Tomorrow {}",
+    no_events: "No upcoming events.",
+    now: "Now: {}",
+    next: "Next: {} at {}",
+    busy_until: "Busy until {}",
+    free: "Free",
+    reserved: "Reserved",
+    busy: "Busy",
+    starting_now: "STARTING NOW",
+    ending_soon: "Ending soon",
+    just_ended: "Just ended",
+    abbreviations: ["d", "h", "m", "s"],
+    days: ["day", "days", "days"],
+    hours: ["hour", "hours", "hours"],
+    weekdays: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    months: [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ],
+    day_first: false,
+    moved: "{} moved to {}",
+    rescheduled: "{} now at {}",
+    cancelled: "{} CANCELLED",
+};
+
+const ES: Catalog = Catalog {
+    starts_in: "En {}",
+    left: "Quedan {}",
+    tomorrow: "Mañana {}",
+    no_events: "No hay eventos.",
+    now: "Ahora: {}",
+    next: "Luego: {} a las {}",
+    busy_until: "Ocupado hasta {}",
+    free: "Libre",
+    reserved: "Reservado",
+    busy: "Ocupado",
+    starting_now: "YA EMPIEZA",
+    ending_soon: "Casi termina",
+    just_ended: "Terminó",
+    abbreviations: ["d", "h", "m", "s"],
+    days: ["día", "días", "días"],
+    hours: ["hora", "horas", "horas"],
+    weekdays: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    months: [
+        "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+    ],
+    day_first: true,
+    moved: "{} se mudó a {}",
+    rescheduled: "{} ahora a las {}",
+    cancelled: "{} CANCELADO",
+};
+
+const DE: Catalog = Catalog {
+    starts_in: "In {}",
+    left: "Noch {}",
+    tomorrow: "Morgen {}",
+    no_events: "Keine Termine.",
+    now: "Jetzt: {}",
+    next: "Danach: {} um {}",
+    busy_until: "Belegt bis {}",
+    free: "Frei",
+    reserved: "Reserviert",
+    busy: "Belegt",
+    starting_now: "BEGINNT JETZT",
+    ending_soon: "Endet bald",
+    just_ended: "Vorbei",
+    abbreviations: ["T", "h", "m", "s"],
+    days: ["Tag", "Tagen", "Tagen"],
+    hours: ["Stunde", "Stunden", "Stunden"],
+    weekdays: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+    months: [
+        "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+    ],
+    day_first: true,
+    moved: "{} jetzt in {}",
+    rescheduled: "{} jetzt um {}",
+    cancelled: "{} ABGESAGT",
+};
+
+const FR: Catalog = Catalog {
+    starts_in: "Dans {}",
+    left: "Reste {}",
+    tomorrow: "Demain {}",
+    no_events: "Aucun événement.",
+    now: "En cours: {}",
+    next: "Ensuite: {} à {}",
+    busy_until: "Occupé jusqu'à {}",
+    free: "Libre",
+    reserved: "Réservé",
+    busy: "Occupé",
+    starting_now: "C'EST PARTI",
+    ending_soon: "Finit bientôt",
+    just_ended: "Terminé",
+    abbreviations: ["j", "h", "m", "s"],
+    days: ["jour", "jours", "jours"],
+    hours: ["heure", "heures", "heures"],
+    weekdays: ["lun", "mar", "mer", "jeu", "ven", "sam", "dim"],
+    months: [
+        "janv", "févr", "mars", "avr", "mai", "juin", "juil", "août", "sept", "oct", "nov", "déc",
+    ],
+    day_first: true,
+    moved: "{} déplacé à {}",
+    rescheduled: "{} maintenant à {}",
+    cancelled: "{} ANNULÉ",
+};
+
+const PL: Catalog = Catalog {
+    starts_in: "Za {}",
+    left: "Zostało {}",
+    tomorrow: "Jutro {}",
+    no_events: "Brak wydarzeń.",
+    now: "Teraz: {}",
+    next: "Potem: {} o {}",
+    busy_until: "Zajęte do {}",
+    free: "Wolne",
+    reserved: "Zarezerwowane",
+    busy: "Zajęte",
+    starting_now: "ZACZYNA SIĘ",
+    ending_soon: "Zaraz koniec",
+    just_ended: "Zakończone",
+    abbreviations: ["d", "h", "m", "s"],
+    days: ["dzień", "dni", "dni"],
+    hours: ["godzinę", "godziny", "godzin"],
+    weekdays: ["pon", "wto", "śro", "czw", "pią", "sob", "nie"],
+    months: [
+        "sty", "lut", "mar", "kwi", "maj", "cze", "lip", "sie", "wrz", "paź", "lis", "gru",
+    ],
+    day_first: true,
+    moved: "{} przeniesione do {}",
+    rescheduled: "{} teraz o {}",
+    cancelled: "{} ODWOŁANE",
+};
+
+impl Locale {
+    pub fn catalog(&self) -> &'static Catalog {
+        match self {
+            Locale::En => &EN,
+            Locale::Es => &ES,
+            Locale::De => &DE,
+            Locale::Fr => &FR,
+            Locale::Pl => &PL,
+        }
+    }
+
+    pub fn plural(&self, n: i64) -> Plural {
+        match self {
+            Locale::En | Locale::Es | Locale::De => match n {
+                1 => Plural::One,
+                _ => Plural::Other,
+            },
+            // French treats zero as singular
+            Locale::Fr => match n {
+                0 | 1 => Plural::One,
+                _ => Plural::Other,
+            },
+            // 1 godzinę, 2-4 godziny (but not 12-14), 5 godzin
+            Locale::Pl => {
+                if n == 1 {
+                    Plural::One
+                } else if (2..=4).contains(&(n % 10)) && !(12..=14).contains(&(n % 100)) {
+                    Plural::Few
+                } else {
+                    Plural::Other
+                }
+            }
+        }
+    }
+
+    // "3 days", "1 hour"
+    pub fn count(&self, n: i64, unit: Unit) -> String {
+        let forms = match unit {
+            Unit::Day => &self.catalog().days,
+            Unit::Hour => &self.catalog().hours,
+        };
+        let form = match self.plural(n) {
+            Plural::One => forms[0],
+            Plural::Few => forms[1],
+            Plural::Other => forms[2],
+        };
+        format!("{} {}", n, form)
+    }
+
+    // "Fri 19:00"
+    pub fn weekday_time<Tz: TimeZone>(&self, time: &DateTime<Tz>, clock: ClockFormat) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let weekday = self.catalog().weekdays[time.weekday().num_days_from_monday() as usize];
+        format!("{} {}", weekday, clock.time(time))
+    }
+
+    // "Wed Mar 14", or "mié 14 mar"
+    pub fn date<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String {
+        let catalog = self.catalog();
+        let weekday = catalog.weekdays[time.weekday().num_days_from_monday() as usize];
+        let month = catalog.months[time.month0() as usize];
+        match catalog.day_first {
+            true => format!("{} {} {}", weekday, time.day(), month),
+            false => format!("{} {} {}", weekday, month, time.day()),
+        }
+    }
+
+    // "Mar 14 19:00", or "14 mar 19:00"
+    pub fn date_time<Tz: TimeZone>(&self, time: &DateTime<Tz>, clock: ClockFormat) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        let catalog = self.catalog();
+        let month = catalog.months[time.month0() as usize];
+        match catalog.day_first {
+            true => format!("{} {} {}", time.day(), month, clock.time(time)),
+            false => format!("{} {} {}", month, time.day(), clock.time(time)),
+        }
+    }
+}
+
+impl Catalog {
+    // Fill a template from the catalog
+    pub fn fill(template: &str, value: &str) -> String {
+        template.replacen("{}", value, 1)
+    }
+
+    // Fill a template with two values, in order
+    pub fn fill2(template: &str, first: &str, second: &str) -> String {
+        match template.split_once("{}") {
+            Some((head, tail)) => format!("{}{}{}", head, first, Self::fill(tail, second)),
+            None => template.to_string(),
+        }
+    }
+}
+
+impl ClockFormat {
+    // 19:05, or 7:05pm
+    pub fn time<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        match self {
+            ClockFormat::H24 => time.format("%H:%M").to_string(),
+            ClockFormat::H12 => time.format("%-I:%M%P").to_string(),
+        }
+    }
+
+    // 19:00, or 7pm. Drops the minutes when they're zero in 12 hour time.
+    pub fn hour<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        match self {
+            ClockFormat::H12 if time.minute() == 0 => time.format("%-I%P").to_string(),
+            _ => self.time(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::locale::{Catalog, ClockFormat, Locale, Plural, Unit};
+    use chrono::prelude::*;
+    use display_core::charset::lcd_safe;
+
+    const LOCALES: [Locale; 5] = [Locale::En, Locale::Es, Locale::De, Locale::Fr, Locale::Pl];
+
+    #[test]
+    fn test_plural_rules() {
+        assert_eq!(Locale::En.plural(0), Plural::Other);
+        assert_eq!(Locale::En.plural(1), Plural::One);
+        assert_eq!(Locale::Fr.plural(0), Plural::One);
+        assert_eq!(Locale::Fr.plural(2), Plural::Other);
+        assert_eq!(Locale::Pl.plural(1), Plural::One);
+        assert_eq!(Locale::Pl.plural(3), Plural::Few);
+        assert_eq!(Locale::Pl.plural(5), Plural::Other);
+        assert_eq!(Locale::Pl.plural(12), Plural::Other);
+        assert_eq!(Locale::Pl.plural(22), Plural::Few);
+
+        assert_eq!(Locale::En.count(1, Unit::Hour), "1 hour");
+        assert_eq!(Locale::En.count(3, Unit::Day), "3 days");
+        assert_eq!(Locale::Pl.count(2, Unit::Hour), "2 godziny");
+        assert_eq!(Locale::Pl.count(5, Unit::Hour), "5 godzin");
+    }
+
+    #[test]
+    fn test_absolute_times() {
+        let t = Utc.with_ymd_and_hms(2024, 3, 14, 19, 0, 0).unwrap();
+        assert_eq!(Locale::En.weekday_time(&t, ClockFormat::H24), "Thu 19:00");
+        assert_eq!(Locale::En.weekday_time(&t, ClockFormat::H12), "Thu 7:00pm");
+        assert_eq!(Locale::En.date_time(&t, ClockFormat::H24), "Mar 14 19:00");
+        assert_eq!(Locale::De.date_time(&t, ClockFormat::H24), "14 Mär 19:00");
+        assert_eq!(ClockFormat::H12.hour(&t), "7pm");
+        assert_eq!(ClockFormat::H24.hour(&t), "19:00");
+    }
+
+    #[test]
+    fn test_templates() {
+        assert_eq!(Catalog::fill(Locale::Es.catalog().left, "2h"), "Quedan 2h");
+        assert_eq!(Catalog::fill(Locale::En.catalog().left, "2h"), "2h Left");
+    }
+
+    // Every string in every catalog has to come out the other side of
+    // lcd_safe without falling back to '?'
+    #[test]
+    fn test_catalogs_are_renderable() {
+        for locale in LOCALES {
+            let c = locale.catalog();
+            let mut strings = vec![
+                c.starts_in,
+                c.left,
+                c.tomorrow,
+                c.no_events,
+                c.now,
+                c.next,
+                c.busy_until,
+                c.free,
+                c.reserved,
+                c.busy,
+                c.starting_now,
+                c.ending_soon,
+                c.just_ended,
+                c.moved,
+                c.rescheduled,
+                c.cancelled,
+            ];
+            strings.extend(c.abbreviations);
+            strings.extend(c.days);
+            strings.extend(c.hours);
+            strings.extend(c.weekdays);
+            strings.extend(c.months);
+            for s in strings {
+                assert!(!lcd_safe(s).contains('?'), "{:?}: {}", locale, s);
+            }
+        }
+    }
+}
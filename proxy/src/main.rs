@@ -1,61 +1,863 @@
-use actix_web::{get, web, App, HttpServer, Responder};
+use actix_web::{
+    dev::Payload, get, web, App, FromRequest, HttpRequest, HttpResponse, HttpResponseBuilder,
+    HttpServer, Responder,
+};
 use chrono::{DateTime, Utc};
 use convert_case::{Case, Casing};
+use display_core::proxy::{
+    EMERGENCY_HEADER, FLASH_HEADER, POWER_HEADER, REFRESH_HEADER, URGENCY_HEADER,
+};
 use dotenv::dotenv;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 
-use std::env;
+use std::{
+    collections::HashMap,
+    env,
+    future::{ready, Ready},
+    sync::Arc,
+};
 
-use async_mutex::Mutex;
+use async_mutex::{Mutex, MutexGuard};
 
+pub mod announcement;
 pub mod calendar;
-use calendar::CalendarEvents;
+pub mod change;
+pub mod clock;
+pub mod config;
+pub mod countdown;
+pub mod emergency;
+pub mod image;
+pub mod locale;
+pub mod metadata;
+pub mod playlist;
+pub mod plugin;
+pub mod power;
+pub mod preview;
+pub mod privacy;
+pub mod render;
+pub mod schema;
+pub mod script;
+pub mod source;
+pub mod target;
+pub mod urgency;
+pub mod webhook;
+use announcement::{Announcement, AnnouncementRequest, Audience, Store};
+use calendar::{CalendarEvents, Timeline};
+use clock::{Clock, SystemClock};
+use config::{Config, GroupConfig, LocationConfig};
+use emergency::{Broadcast, BroadcastRequest, Broadcasts};
+use image::Size;
+use playlist::Content;
+use power::Power;
+use preview::Panel;
+use render::{Geometry, Layout, Screen};
+use script::{Outcome, Scripts};
+use source::{Item, Sources};
+use target::{Target, TargetRequest, Targets};
+use urgency::Urgency;
+use webhook::{DeadLetters, Watcher};
 
 struct EventCache {
     events: Mutex<CalendarEvents>,
     last_update: Mutex<DateTime<Utc>>,
 }
 
-async fn screen(cache: web::Data<EventCache>, location: web::Path<String>) -> String {
-    println!("Get calendar events for {}", location);
-    let mut last_update = cache.last_update.lock().await;
-    let mut events = cache.events.lock().await;
+impl EventCache {
+    // Hand out the events, refreshing them first if they're older than
+    // CACHE_TTL
+    async fn get(&self, now: DateTime<Utc>) -> anyhow::Result<MutexGuard<'_, CalendarEvents>> {
+        let mut last_update = self.last_update.lock().await;
+        let mut events = self.events.lock().await;
+
+        // Check if we need to update.
+        let ttl: i64 = match env::var("CACHE_TTL") {
+            Ok(t) => t.parse::<i64>().unwrap(),
+            Err(_) => 30,
+        };
+
+        // How close to its start a change to an event gets mentioned
+        let window: i64 = match env::var("CHANGE_WINDOW") {
+            Ok(w) => w.parse::<i64>().unwrap(),
+            Err(_) => change::DEFAULT_WINDOW_MINUTES,
+        };
+
+        if now > *last_update + chrono::Duration::try_seconds(ttl).unwrap() {
+            print!("Refreshing cache...");
+            (*events)
+                .update(now, chrono::Duration::minutes(window))
+                .await?;
+            *last_update = now;
+            println!(" done");
+        }
+
+        Ok(events)
+    }
+}
+
+// Lets a device tell us about itself, and ask for something other than
+// its location's defaults
+#[derive(Deserialize)]
+struct DisplayQuery {
+    layout: Option<Layout>,
+    rows: Option<usize>,
+    cols: Option<usize>,
+    // Draw the screen as it would be at this moment, rather than now
+    at: Option<String>,
+}
+
+impl DisplayQuery {
+    fn geometry(&self) -> Result<Geometry, String> {
+        let default = Geometry::default();
+        let rows = self.rows.unwrap_or(default.rows);
+        let cols = self.cols.unwrap_or(default.cols);
+        Geometry::new(rows, cols).ok_or(format!("Unsupported display size {}x{}", cols, rows))
+    }
+
+    // The moment to draw the screen for
+    fn now(&self, clock: &dyn Clock) -> Result<DateTime<Utc>, String> {
+        match &self.at {
+            // A + in a query string comes through as a space, and nobody
+            // remembers to escape the one in a UTC offset
+            Some(at) => DateTime::parse_from_rfc3339(&at.replace(' ', "+"))
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|e| format!("Bad time {}: {}", at, e)),
+            None => Ok(clock.now()),
+        }
+    }
+
+    // The panel to lay out for, and the moment to draw it at
+    fn target(&self, clock: &dyn Clock) -> Result<(Geometry, DateTime<Utc>), String> {
+        Ok((self.geometry()?, self.now(clock)?))
+    }
+}
+
+// How big to draw a picture of a screen
+#[derive(Deserialize)]
+struct ImageQuery {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+impl ImageQuery {
+    fn size(&self) -> Result<Size, String> {
+        let default = Size::default();
+        let width = self.width.unwrap_or(default.width);
+        let height = self.height.unwrap_or(default.height);
+        Size::new(width, height).ok_or(format!("Unsupported image size {}x{}", width, height))
+    }
+}
+
+// Send a screen to the device
+fn respond(
+    screen: Screen,
+    now: DateTime<Utc>,
+    location_config: &LocationConfig,
+    geometry: Geometry,
+) -> HttpResponse {
+    let text = screen.to_text(now, location_config, geometry);
+    directives(&screen).body(text)
+}
+
+// Everything the device is told besides the text itself
+fn directives(screen: &Screen) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    if let Some(refresh) = screen.refresh {
+        response.insert_header((REFRESH_HEADER, refresh.num_milliseconds().to_string()));
+    }
+    if screen.urgency != Urgency::None {
+        response.insert_header((URGENCY_HEADER, screen.urgency.name()));
+    }
+    if screen.flash {
+        response.insert_header((FLASH_HEADER, "1"));
+    }
+    if screen.power != Power::On {
+        response.insert_header((POWER_HEADER, screen.power.name()));
+    }
+    if screen.emergency {
+        response.insert_header((EMERGENCY_HEADER, "1"));
+    }
+    response
+}
 
-    // Check if we need to update.
-    let ttl: i64 = match env::var("CACHE_TTL") {
-        Ok(t) => t.parse::<i64>().unwrap(),
-        Err(_) => 30,
+fn calendar_error(e: anyhow::Error) -> HttpResponse {
+    let msg = format!("Failed to get calendar events: {}", e).to_string();
+    println!("{}", msg);
+    HttpResponse::Ok().body(msg)
+}
+
+// What a sign at this location would be showing. Runs the location's
+// script, if it has one, so keep it off the async threads.
+fn location_display(
+    signs: &Signs,
+    events: &CalendarEvents,
+    notices: &Notices,
+    location: &str,
+    layout: Option<Layout>,
+    now: DateTime<Utc>,
+    geometry: Geometry,
+) -> (Screen, LocationConfig) {
+    let config = &signs.config;
+    let location = location.to_case(Case::Title);
+    let location_config = config.location(&location);
+
+    let timeline = privacy::redact(&events.timeline_at_location(&location), &location_config);
+    let announcements: Vec<&Announcement> = notices
+        .announcements
+        .iter()
+        .filter(|a| a.reaches(Audience::Location(&location), config))
+        .collect();
+    let targets: Vec<&Target> = notices
+        .targets
+        .iter()
+        .filter(|t| t.reaches(&location, config))
+        .collect();
+
+    // A device that asks for a layout gets it. Otherwise, it's whatever's
+    // up on the location's playlist, if it has one.
+    let items = |slot: &playlist::Slot| -> &[Item] {
+        slot.source
+            .as_ref()
+            .and_then(|s| notices.items.get(s))
+            .map_or(&[], |items| items.as_slice())
     };
+    let turn = match layout {
+        Some(_) => None,
+        None => playlist::schedule(
+            &location_config.playlist,
+            timeline.current(now).is_some(),
+            |slot| match slot.content {
+                Content::Announcements => announcements.iter().any(|a| a.is_live(now)),
+                Content::Countdowns => !targets.is_empty(),
+                Content::Ticker => !items(slot).is_empty(),
+                _ => true,
+            },
+            now,
+        ),
+    };
+    let content = turn.map_or(Content::Events, |t| t.slot.content);
+    let mut screen = match content {
+        Content::Announcements => {
+            announcement::board(&announcements, &timeline, now, &location_config, geometry)
+        }
+        Content::Countdowns => target::screen(&targets, &timeline, now, &location_config, geometry),
+        _ => {
+            let asked = layout.is_some();
+            let layout = match turn {
+                Some(turn) => turn.slot.content.layout(location_config.layout),
+                None => layout,
+            };
+            let layout = layout.unwrap_or(location_config.layout);
+            let mut screen = render::render(layout, &timeline, now, &location_config, geometry);
 
-    if Utc::now() > *last_update + chrono::Duration::try_seconds(ttl).unwrap() {
-        print!("Refreshing cache...");
-        match (*events).update().await {
-            Ok(_) => {
-                *last_update = Utc::now();
-                println!(" done");
-            }
-            Err(e) => {
-                let msg = format!("Failed to get calendar events: {}", e).to_string();
-                println!("{}", msg);
-                return msg;
+            // The location's own rules for its events, unless the device
+            // asked for something in particular
+            let script = location_config
+                .script
+                .as_deref()
+                .filter(|_| !asked && content == Content::Events);
+            if let Some(path) = script {
+                let rows = &screen.rows;
+                let scripts = &signs.scripts;
+                match scripts.run(path, &timeline, rows, now, &location_config, geometry) {
+                    Some(Outcome::Rows(rows)) => {
+                        screen.rows = rows;
+                        screen.page = None;
+                    }
+                    Some(Outcome::Layout(layout)) => {
+                        screen = render::render(layout, &timeline, now, &location_config, geometry);
+                    }
+                    None => (),
+                }
             }
+            screen
+        }
+    };
+    if content != Content::Announcements {
+        // Word of anything that was meant to be here, and isn't anymore
+        let changes = events.changes_at_location(&location);
+        let busy = timeline.current(now).is_some();
+        change::apply(&mut screen, &changes, busy, now, &location_config, geometry);
+        announcement::apply(&mut screen, &announcements, now, geometry);
+    }
+    if let Some(turn) = turn.filter(|t| t.slot.content == Content::Ticker) {
+        source::ticker(
+            &mut screen,
+            items(turn.slot),
+            now,
+            &location_config,
+            geometry,
+        );
+    }
+    if let Some(turn) = turn {
+        screen.refresh_at(turn.until, now);
+    }
+    if let Some(broadcast) = &notices.broadcast {
+        emergency::apply(&mut screen, broadcast);
+    }
+    (screen, location_config)
+}
+
+// What a sign covering a group of locations would be showing
+fn group_display(
+    events: &CalendarEvents,
+    notices: &Notices,
+    config: &Config,
+    group: &str,
+    group_config: &GroupConfig,
+    now: DateTime<Utc>,
+    geometry: Geometry,
+) -> Screen {
+    let members: Vec<(String, Timeline)> = group_config
+        .locations
+        .iter()
+        .map(|l| {
+            // Each room's own privacy rules, then the sign's
+            let timeline = privacy::redact(&events.timeline_at_location(l), &config.location(l));
+            (l.clone(), privacy::redact(&timeline, &group_config.display))
+        })
+        .collect();
+    let mut screen = render::group(&members, now, &group_config.display, geometry);
+    let announcements: Vec<&Announcement> = notices
+        .announcements
+        .iter()
+        .filter(|a| a.reaches(Audience::Group(group), config))
+        .collect();
+    announcement::apply(&mut screen, &announcements, now, geometry);
+    if let Some(broadcast) = &notices.broadcast {
+        emergency::apply(&mut screen, broadcast);
+    }
+    screen
+}
+
+// Everything besides the calendar that goes on the signs
+struct Notices {
+    announcements: Vec<Announcement>,
+    broadcast: Option<Broadcast>,
+    targets: Vec<Target>,
+    // What each source had, by name
+    items: HashMap<String, Vec<Item>>,
+}
+
+// Notices for the moment being drawn. A database that's down or a feed
+// that's broken shouldn't take the signs down with it.
+async fn notices(signs: &Signs, now: DateTime<Utc>) -> Notices {
+    let broadcast = signs.broadcasts.current();
+    let targets = signs.targets.current(now);
+    let items = signs.sources.items();
+    let store = signs.store.clone();
+    let announcements = match blocking(move || store.current(now)).await {
+        Ok(found) => found,
+        Err(e) => {
+            println!("Failed to get announcements: {}", e);
+            vec![]
+        }
+    };
+    Notices {
+        announcements,
+        broadcast,
+        targets,
+        items,
+    }
+}
+
+// Everything a sign's screen gets drawn from. Each of these is app data of
+// its own; this just saves every handler from asking for them one by one.
+#[derive(Clone)]
+struct Signs {
+    cache: web::Data<EventCache>,
+    clock: web::Data<dyn Clock>,
+    config: web::Data<Config>,
+    store: web::Data<Store>,
+    broadcasts: web::Data<Broadcasts>,
+    targets: web::Data<Targets>,
+    sources: web::Data<Sources>,
+    scripts: web::Data<Scripts>,
+}
+
+fn app_data<T: ?Sized + 'static>(req: &HttpRequest) -> Result<web::Data<T>, actix_web::Error> {
+    req.app_data::<web::Data<T>>()
+        .cloned()
+        .ok_or(actix_web::error::ErrorInternalServerError(
+            "Missing app data",
+        ))
+}
+
+impl FromRequest for Signs {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let signs = || {
+            Ok(Signs {
+                cache: app_data(req)?,
+                clock: app_data(req)?,
+                config: app_data(req)?,
+                store: app_data(req)?,
+                broadcasts: app_data(req)?,
+                targets: app_data(req)?,
+                sources: app_data(req)?,
+                scripts: app_data(req)?,
+            })
         };
+        ready(signs())
     }
+}
+
+impl Signs {
+    // The calendar as it stands, and whatever's being announced over it.
+    // Drawing a sign can take a while, so it gets a copy of the calendar
+    // rather than holding on to it.
+    async fn read(&self, now: DateTime<Utc>) -> Result<(CalendarEvents, Notices), HttpResponse> {
+        // Before the calendar's locked, so nobody's kept waiting on the
+        // database while we're at it
+        let notices = notices(self, now).await;
+        let events = self
+            .cache
+            .get(self.clock.now())
+            .await
+            .map_err(calendar_error)?
+            .clone();
+        Ok((events, notices))
+    }
+}
 
-    let event_text = match (*events).get_next_at_location(&location.to_case(Case::Title)) {
-        Some(e) => e.format_2004(),
-        None => "No upcoming events.".to_string(),
+// Which sign to draw
+enum Sign<'a> {
+    Location(&'a str),
+    Group(&'a str),
+}
+
+// What a sign would be showing for a device's query, along with the
+// settings, panel and moment it was drawn for. Or, what to say instead.
+async fn display(
+    signs: &Signs,
+    sign: Sign<'_>,
+    query: &DisplayQuery,
+) -> Result<(Screen, LocationConfig, Geometry, DateTime<Utc>), HttpResponse> {
+    let (geometry, now) = query
+        .target(&**signs.clock)
+        .map_err(|e| HttpResponse::BadRequest().body(e))?;
+    let (screen, location_config) = match sign {
+        Sign::Location(location) => {
+            let (events, notices) = signs.read(now).await?;
+            let (signs, location, layout) = (signs.clone(), location.to_string(), query.layout);
+            web::block(move || {
+                location_display(&signs, &events, &notices, &location, layout, now, geometry)
+            })
+            .await
+            .map_err(|e| HttpResponse::InternalServerError().body(e.to_string()))?
+        }
+        Sign::Group(group) => {
+            let name = group.to_case(Case::Title);
+            let group_config = signs.config.group(&name).ok_or_else(|| {
+                HttpResponse::NotFound().body(format!("No group named {}", group))
+            })?;
+            let (events, notices) = signs.read(now).await?;
+            let screen = group_display(
+                &events,
+                &notices,
+                &signs.config,
+                &name,
+                &group_config,
+                now,
+                geometry,
+            );
+            (screen, group_config.display)
+        }
     };
+    Ok((screen, location_config, geometry, now))
+}
 
-    // Lol this only works in Eastern
-    let now = chrono::offset::Utc::now()
-        .with_timezone(&chrono_tz::US::Eastern)
-        .format("%H:%M");
-    let mut time_text = format!("[{}]", now);
-    time_text = format!("{: >width$}", time_text, width = 20);
+async fn screen(
+    signs: Signs,
+    location: web::Path<String>,
+    query: web::Query<DisplayQuery>,
+) -> HttpResponse {
+    println!("Get calendar events for {}", location);
+    match display(&signs, Sign::Location(&location), &query).await {
+        Ok((screen, location_config, geometry, now)) => {
+            respond(screen, now, &location_config, geometry)
+        }
+        Err(response) => response,
+    }
+}
 
-    match event_text.lines().count() {
-        1 => format!("{}\n\n\n{}", event_text, time_text),
-        _ => format!("{}\n\n{}", event_text, time_text),
+// One sign, several rooms
+async fn group_screen(
+    signs: Signs,
+    group: web::Path<String>,
+    query: web::Query<DisplayQuery>,
+) -> HttpResponse {
+    println!("Get calendar events for group {}", group);
+    match display(&signs, Sign::Group(&group), &query).await {
+        Ok((screen, location_config, geometry, now)) => {
+            respond(screen, now, &location_config, geometry)
+        }
+        Err(response) => response,
+    }
+}
+
+// Send a screen as a picture
+fn respond_png(
+    screen: Screen,
+    now: DateTime<Utc>,
+    location_config: &LocationConfig,
+    geometry: Geometry,
+    size: Size,
+) -> HttpResponse {
+    let text = screen.to_text(now, location_config, geometry);
+    let data = match image::png(&text, geometry, size) {
+        Ok(data) => data,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    directives(&screen).content_type("image/png").body(data)
+}
+
+async fn image(
+    signs: Signs,
+    sign: Sign<'_>,
+    query: &DisplayQuery,
+    image_query: &ImageQuery,
+) -> HttpResponse {
+    let size = match image_query.size() {
+        Ok(size) => size,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match display(&signs, sign, query).await {
+        Ok((screen, location_config, geometry, now)) => {
+            respond_png(screen, now, &location_config, geometry, size)
+        }
+        Err(response) => response,
+    }
+}
+
+async fn location_image(
+    signs: Signs,
+    location: web::Path<String>,
+    query: web::Query<DisplayQuery>,
+    image_query: web::Query<ImageQuery>,
+) -> HttpResponse {
+    image(signs, Sign::Location(&location), &query, &image_query).await
+}
+
+async fn group_image(
+    signs: Signs,
+    group: web::Path<String>,
+    query: web::Query<DisplayQuery>,
+    image_query: web::Query<ImageQuery>,
+) -> HttpResponse {
+    image(signs, Sign::Group(&group), &query, &image_query).await
+}
+
+fn html(page: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page)
+}
+
+// A sign, drawn for a browser
+async fn preview(signs: Signs, name: &str, sign: Sign<'_>, query: &DisplayQuery) -> HttpResponse {
+    match display(&signs, sign, query).await {
+        Ok((screen, location_config, geometry, now)) => {
+            let name = name.to_case(Case::Title);
+            let panel = Panel {
+                name: name.clone(),
+                link: None,
+                text: screen.to_text(now, &location_config, geometry),
+                geometry,
+                refresh: screen.refresh,
+            };
+            html(preview::page(&name, &[panel]))
+        }
+        Err(response) => response,
+    }
+}
+
+async fn location_preview(
+    signs: Signs,
+    location: web::Path<String>,
+    query: web::Query<DisplayQuery>,
+) -> HttpResponse {
+    preview(signs, &location, Sign::Location(&location), &query).await
+}
+
+async fn group_preview(
+    signs: Signs,
+    group: web::Path<String>,
+    query: web::Query<DisplayQuery>,
+) -> HttpResponse {
+    preview(signs, &group, Sign::Group(&group), &query).await
+}
+
+// Every sign in the config file, all at once
+async fn wall(signs: Signs, query: web::Query<DisplayQuery>) -> HttpResponse {
+    let (geometry, now) = match query.target(&**signs.clock) {
+        Ok(t) => t,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let (events, notices) = match signs.read(now).await {
+        Ok(read) => read,
+        Err(response) => return response,
+    };
+    // Every location's script runs in here, so it's off the async threads
+    let layout = query.layout;
+    let panels = web::block(move || {
+        let config = &signs.config;
+
+        let mut locations: Vec<&String> = config.locations.keys().collect();
+        locations.sort();
+        let mut groups: Vec<(&String, &GroupConfig)> = config.groups.iter().collect();
+        groups.sort_by_key(|(name, _)| *name);
+
+        let mut panels = vec![];
+        for location in locations {
+            let (screen, location_config) =
+                location_display(&signs, &events, &notices, location, layout, now, geometry);
+            panels.push(Panel {
+                name: location.clone(),
+                link: Some(format!(
+                    "/locations/{}/preview",
+                    location.to_case(Case::Kebab)
+                )),
+                text: screen.to_text(now, &location_config, geometry),
+                geometry,
+                refresh: screen.refresh,
+            });
+        }
+        for (group, group_config) in groups {
+            let screen = group_display(
+                &events,
+                &notices,
+                config,
+                group,
+                group_config,
+                now,
+                geometry,
+            );
+            panels.push(Panel {
+                name: group.clone(),
+                link: Some(format!("/groups/{}/preview", group.to_case(Case::Kebab))),
+                text: screen.to_text(now, &group_config.display, geometry),
+                geometry,
+                refresh: screen.refresh,
+            });
+        }
+        panels
+    })
+    .await;
+    let panels = match panels {
+        Ok(panels) => panels,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string()),
+    };
+    html(preview::page("Sidegrade", &panels))
+}
+
+// Run database work off the async threads
+async fn blocking<T, F>(f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+{
+    web::block(f).await?
+}
+
+// The admin API wants ADMIN_TOKEN as a bearer token. Without one set,
+// there's no admin API. Hands back what to say instead if the request
+// doesn't have it.
+fn refuse(req: &HttpRequest) -> Option<HttpResponse> {
+    let token = match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return Some(HttpResponse::Forbidden().body("Set ADMIN_TOKEN to use the admin API")),
+    };
+    let given = req
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    // Compared in constant time, and hashed first so the length doesn't
+    // give anything away either
+    let matches = given.is_some_and(|given| {
+        Sha256::digest(given.as_bytes())
+            .ct_eq(&Sha256::digest(token.as_bytes()))
+            .into()
+    });
+    match matches {
+        true => None,
+        false => Some(HttpResponse::Unauthorized().body("Bad or missing admin token")),
+    }
+}
+
+fn database_error(e: anyhow::Error) -> HttpResponse {
+    let msg = format!("Database error: {}", e);
+    println!("{}", msg);
+    HttpResponse::InternalServerError().body(msg)
+}
+
+// Everything that hasn't expired, including what's yet to start
+async fn list_announcements(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    store: web::Data<Store>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let now = clock.now();
+    let store = store.clone();
+    match blocking(move || store.current(now)).await {
+        Ok(found) => HttpResponse::Ok().json(found),
+        Err(e) => database_error(e),
+    }
+}
+
+async fn post_announcement(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    store: web::Data<Store>,
+    body: web::Json<AnnouncementRequest>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let new = match body.into_inner().validate(clock.now()) {
+        Ok(new) => new,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let store = store.clone();
+    match blocking(move || store.create(new)).await {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => database_error(e),
+    }
+}
+
+// Take an announcement down before it expires
+async fn delete_announcement(
+    req: HttpRequest,
+    store: web::Data<Store>,
+    id: web::Path<i32>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let (store, id) = (store.clone(), id.into_inner());
+    match blocking(move || store.delete(id)).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().body(format!("No announcement {}", id)),
+        Err(e) => database_error(e),
+    }
+}
+
+// The fast poll. Devices ask this every couple of seconds, so it only
+// looks at what's in memory, and has nothing to say most of the time.
+async fn get_emergency(
+    broadcasts: web::Data<Broadcasts>,
+    query: web::Query<DisplayQuery>,
+) -> HttpResponse {
+    let geometry = match query.geometry() {
+        Ok(geometry) => geometry,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    match broadcasts.current() {
+        Some(broadcast) => HttpResponse::Ok()
+            .insert_header((EMERGENCY_HEADER, "1"))
+            .insert_header((FLASH_HEADER, "1"))
+            .body(emergency::to_text(&broadcast, geometry)),
+        None => HttpResponse::NoContent().finish(),
+    }
+}
+
+async fn post_emergency(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    broadcasts: web::Data<Broadcasts>,
+    body: web::Json<BroadcastRequest>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let message = body.into_inner().message;
+    if message.trim().is_empty() {
+        return HttpResponse::BadRequest().body("A broadcast needs a message");
+    }
+    let now = clock.now();
+    let broadcasts = broadcasts.clone();
+    match blocking(move || broadcasts.start(message, now)).await {
+        Ok(broadcast) => HttpResponse::Created().json(broadcast),
+        Err(e) => database_error(e),
+    }
+}
+
+// All clear
+async fn delete_emergency(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    broadcasts: web::Data<Broadcasts>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let now = clock.now();
+    let broadcasts = broadcasts.clone();
+    match blocking(move || broadcasts.clear(now)).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().body("No emergency broadcast going"),
+        Err(e) => database_error(e),
+    }
+}
+
+fn countdown_error(e: anyhow::Error) -> HttpResponse {
+    let msg = format!("Failed to save countdowns: {}", e);
+    println!("{}", msg);
+    HttpResponse::InternalServerError().body(msg)
+}
+
+// Countdowns that haven't passed yet
+async fn list_countdowns(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    targets: web::Data<Targets>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    HttpResponse::Ok().json(targets.current(clock.now()))
+}
+
+async fn post_countdown(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    targets: web::Data<Targets>,
+    body: web::Json<TargetRequest>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let (targets, request, now) = (targets.clone(), body.into_inner(), clock.now());
+    if let Err(e) = request.validate(now) {
+        return HttpResponse::BadRequest().body(e);
+    }
+    match blocking(move || targets.create(request, now)).await {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => countdown_error(e),
+    }
+}
+
+async fn delete_countdown(
+    req: HttpRequest,
+    clock: web::Data<dyn Clock>,
+    targets: web::Data<Targets>,
+    id: web::Path<u32>,
+) -> HttpResponse {
+    if let Some(response) = refuse(&req) {
+        return response;
+    }
+    let (targets, id, now) = (targets.clone(), id.into_inner(), clock.now());
+    match blocking(move || targets.delete(id, now)).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().body(format!("No countdown {}", id)),
+        Err(e) => countdown_error(e),
     }
 }
 
@@ -73,7 +875,7 @@ async fn reserve(
         .expect("Failed to parse timestamp")
         .with_timezone(&Utc);
 
-    let upcoming_events = CalendarEvents::new().await;
+    let upcoming_events = CalendarEvents::new(Utc::now()).await;
     match upcoming_events {
         Ok(u) => {
             if u.is_free_at_location(&location, proposed_start, proposed_end) {
@@ -85,6 +887,68 @@ async fn reserve(
     }
 }
 
+// Look in on every room with webhooks every so often, and send word of
+// anything that's changed
+async fn watch_webhooks(
+    cache: web::Data<EventCache>,
+    config: web::Data<Config>,
+    clock: web::Data<dyn Clock>,
+) {
+    // Nobody to tell, so don't go asking the calendar every few seconds
+    if config.locations.values().all(|l| l.webhooks.is_empty()) {
+        return;
+    }
+    let mut watcher = Watcher::default();
+    let dead_letters = DeadLetters::open();
+    let mut interval =
+        actix_web::rt::time::interval(std::time::Duration::from_secs(webhook::CHECK_SECONDS));
+    loop {
+        interval.tick().await;
+        let now = clock.now();
+        let events = match cache.get(now).await {
+            Ok(events) => events,
+            Err(e) => {
+                println!("Failed to get calendar events for webhooks: {}", e);
+                continue;
+            }
+        };
+        let mut deliveries = vec![];
+        for (location, location_config) in &config.locations {
+            if location_config.webhooks.is_empty() {
+                continue;
+            }
+            let timeline = privacy::redact(&events.timeline_at_location(location), location_config);
+            for payload in watcher.observe(location, &timeline, now, location_config) {
+                for hook in &location_config.webhooks {
+                    if hook.on.contains(&payload.transition) {
+                        deliveries.push((hook.clone(), payload.clone()));
+                    }
+                }
+            }
+        }
+        drop(events);
+
+        for (hook, payload) in deliveries {
+            let clock = clock.clone().into_inner();
+            actix_web::rt::spawn(webhook::deliver(hook, payload, dead_letters.clone(), clock));
+        }
+    }
+}
+
+// Fetch whatever sources are due every so often, so drawing a sign only
+// ever reads what's already been fetched
+async fn watch_sources(sources: web::Data<Sources>, clock: web::Data<dyn Clock>) {
+    let mut interval =
+        actix_web::rt::time::interval(std::time::Duration::from_secs(source::CHECK_SECONDS));
+    loop {
+        interval.tick().await;
+        let (sources, now) = (sources.clone(), clock.now());
+        if let Err(e) = web::block(move || sources.refresh(now)).await {
+            println!("Failed to refresh sources: {}", e);
+        }
+    }
+}
+
 async fn oh_hi() -> impl Responder {
     "Oh, hi."
 }
@@ -94,17 +958,75 @@ async fn main() -> std::io::Result<()> {
     println!("Launching sidegrade4-proxy");
     println!("Check dotenv");
     dotenv().ok();
+    let config = web::Data::new(Config::load().expect("Failed to load location config"));
+    // None of these are worth keeping the events off the signs for, so
+    // carry on without whichever of them won't load, like with no database
+    let store = Store::connect().unwrap_or_else(|e| {
+        println!(
+            "Failed to connect to database, announcements are off: {}",
+            e
+        );
+        Store::default()
+    });
+    let broadcasts = Broadcasts::load(store.pool.clone()).unwrap_or_else(|e| {
+        println!(
+            "Failed to load emergency broadcast, starting without one: {}",
+            e
+        );
+        Broadcasts::default()
+    });
+    let broadcasts = web::Data::new(broadcasts);
+    let store = web::Data::new(store);
+    let targets = Targets::load().unwrap_or_else(|e| {
+        println!("Failed to load countdowns, they're off: {}", e);
+        Targets::default()
+    });
+    let targets = web::Data::new(targets);
     println!("Run webserver");
 
+    let clock: web::Data<dyn Clock> = web::Data::from(Arc::new(SystemClock) as Arc<dyn Clock>);
     let cache = web::Data::new(EventCache {
-        events: Mutex::new(CalendarEvents::new().await.unwrap()),
-        last_update: Mutex::new(Utc::now()),
+        events: Mutex::new(CalendarEvents::new(clock.now()).await.unwrap()),
+        last_update: Mutex::new(clock.now()),
     });
+    let sources = web::Data::new(Sources::new(&config.sources_in_use()));
+    let scripts = web::Data::new(Scripts::default());
+
+    actix_web::rt::spawn(watch_webhooks(cache.clone(), config.clone(), clock.clone()));
+    actix_web::rt::spawn(watch_sources(sources.clone(), clock.clone()));
 
     HttpServer::new(move || {
         App::new()
             .app_data(cache.clone())
+            .app_data(config.clone())
+            .app_data(clock.clone())
+            .app_data(store.clone())
+            .app_data(broadcasts.clone())
+            .app_data(targets.clone())
+            .app_data(sources.clone())
+            .app_data(scripts.clone())
             .route("/locations/{location}/event", web::get().to(screen))
+            .route("/groups/{group}/event", web::get().to(group_screen))
+            .route(
+                "/locations/{location}/preview",
+                web::get().to(location_preview),
+            )
+            .route("/groups/{group}/preview", web::get().to(group_preview))
+            .route("/wall", web::get().to(wall))
+            .route(
+                "/locations/{location}/image.png",
+                web::get().to(location_image),
+            )
+            .route("/groups/{group}/image.png", web::get().to(group_image))
+            .route("/announcements", web::get().to(list_announcements))
+            .route("/announcements", web::post().to(post_announcement))
+            .route("/announcements/{id}", web::delete().to(delete_announcement))
+            .route("/countdowns", web::get().to(list_countdowns))
+            .route("/countdowns", web::post().to(post_countdown))
+            .route("/countdowns/{id}", web::delete().to(delete_countdown))
+            .route("/emergency", web::get().to(get_emergency))
+            .route("/emergency", web::post().to(post_emergency))
+            .route("/emergency", web::delete().to(delete_emergency))
             .route("/", web::get().to(oh_hi))
     })
     .bind(("0.0.0.0", 8080))?
@@ -0,0 +1,124 @@
+// How organizers tell the signs what to do with their events, without
+// touching the proxy. Options come from the event's private extended
+// properties, or from tagged lines in its description:
+//
+//   sign: Smash Night
+//   sign-line: Bring a controller
+//   sign-pin
+//   sign-hide
+//
+// Extended properties use the same names, and win if both are set.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const TITLE: &str = "sign";
+const LINE: &str = "sign-line";
+const PIN: &str = "sign-pin";
+const HIDE: &str = "sign-hide";
+
+// Fits the extendedProperties field of the Google Calendar API response
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ExtendedProperties {
+    pub private: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignOptions {
+    // Shown instead of the event's title
+    pub title: Option<String>,
+    // An extra line under the countdown, where there's room for it
+    pub line: Option<String>,
+    // Shown ahead of anything sooner, until it's over
+    pub pin: bool,
+    // Left off the sign entirely
+    pub hide: bool,
+}
+
+impl SignOptions {
+    pub fn parse(
+        description: Option<&str>,
+        properties: Option<&ExtendedProperties>,
+    ) -> SignOptions {
+        let mut tags: HashMap<String, String> = HashMap::new();
+        for line in description.map(lines).unwrap_or_default() {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key, value),
+                None => (line.as_str(), ""),
+            };
+            let key = key.trim().to_lowercase();
+            if [TITLE, LINE, PIN, HIDE].contains(&key.as_str()) {
+                tags.insert(key, value.trim().to_string());
+            }
+        }
+        if let Some(properties) = properties {
+            for (key, value) in &properties.private {
+                tags.insert(key.to_lowercase(), value.trim().to_string());
+            }
+        }
+
+        let text = |key| tags.get(key).filter(|v| !v.is_empty()).cloned();
+        // A bare tag is as good as saying yes
+        let flag = |key| {
+            tags.get(key)
+                .is_some_and(|v| matches!(v.to_lowercase().as_str(), "" | "true" | "yes" | "1"))
+        };
+        SignOptions {
+            title: text(TITLE),
+            line: text(LINE),
+            pin: flag(PIN),
+            hide: flag(HIDE),
+        }
+    }
+}
+
+// Descriptions written in Google Calendar come back as HTML, so a line
+// might end with a <br> instead of a newline
+fn lines(description: &str) -> Vec<String> {
+    description
+        .replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::{ExtendedProperties, SignOptions};
+
+    #[test]
+    fn test_parse() {
+        let description = "Monthly tournament, all welcome!<br>sign: Smash Night<br>\nSign-Pin\nsign-line: Bring a controller";
+        let options = SignOptions::parse(Some(description), None);
+        assert_eq!(
+            options,
+            SignOptions {
+                title: Some("Smash Night".to_string()),
+                line: Some("Bring a controller".to_string()),
+                pin: true,
+                hide: false,
+            }
+        );
+
+        // Extended properties win
+        let properties = ExtendedProperties {
+            private: [
+                ("sign".to_string(), "Smash".to_string()),
+                ("sign-pin".to_string(), "false".to_string()),
+                ("sign-hide".to_string(), "true".to_string()),
+            ]
+            .into(),
+        };
+        let options = SignOptions::parse(Some(description), Some(&properties));
+        assert_eq!(options.title.as_deref(), Some("Smash"));
+        assert!(!options.pin);
+        assert!(options.hide);
+
+        // Sentences that happen to mention the sign aren't tags
+        let options = SignOptions::parse(Some("Put up a sign: it's fun"), None);
+        assert_eq!(options, SignOptions::default());
+    }
+}
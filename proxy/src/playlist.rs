@@ -0,0 +1,172 @@
+// Signs that take turns showing different things, like the events for a
+// while, then the time, then the house's announcements. Like paging, which
+// slot is up is worked out from the clock, so there's nothing to keep track
+// of between requests and every sign on the same playlist stays in step.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::render::Layout;
+
+// Something a slot can show
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Content {
+    // The location's own layout
+    Events,
+    Agenda,
+    // Every live announcement for the location. Skipped when there aren't
+    // any.
+    Announcements,
+    // The time and date, big
+    Clock,
+    // Countdowns set through the admin API. Skipped when there aren't any.
+    Countdowns,
+    // The location's layout, with the slot's source running along the
+    // last row. Skipped when the source has nothing.
+    Ticker,
+}
+
+impl Content {
+    // The layout a slot draws with, for the ones that are just layouts
+    pub fn layout(&self, default: Layout) -> Option<Layout> {
+        match self {
+            Content::Events | Content::Ticker => Some(default),
+            Content::Agenda => Some(Layout::Agenda),
+            Content::Clock => Some(Layout::Clock),
+            Content::Announcements | Content::Countdowns => None,
+        }
+    }
+}
+
+// When a slot gets a turn
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    #[default]
+    Always,
+    // Only while nothing's going on in the room
+    Free,
+    Busy,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Slot {
+    pub content: Content,
+    // How long it stays up each time round
+    #[serde(default = "default_dwell")]
+    pub dwell_seconds: i64,
+    #[serde(default)]
+    pub when: Condition,
+    // Only the highest priority slots that can go take turns, so a slot can
+    // take over while its condition holds
+    #[serde(default)]
+    pub priority: i32,
+    // Which source a ticker shows
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+fn default_dwell() -> i64 {
+    10
+}
+
+// What's up right now
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Turn<'a> {
+    pub slot: &'a Slot,
+    // When the next slot is due
+    pub until: DateTime<Utc>,
+}
+
+// Pick the slot that's up at `now`. `busy` is whether the room has an event
+// going, and `ready` whether a slot has anything to show.
+// With nothing that can go, it's up to the caller what the sign shows.
+pub fn schedule<'a>(
+    slots: &'a [Slot],
+    busy: bool,
+    ready: impl Fn(&Slot) -> bool,
+    now: DateTime<Utc>,
+) -> Option<Turn<'a>> {
+    let eligible: Vec<&Slot> = slots
+        .iter()
+        .filter(|s| match s.when {
+            Condition::Always => true,
+            Condition::Free => !busy,
+            Condition::Busy => busy,
+        })
+        .filter(|s| ready(s))
+        .collect();
+    let top = eligible.iter().map(|s| s.priority).max()?;
+    let turns: Vec<&Slot> = eligible.into_iter().filter(|s| s.priority == top).collect();
+
+    // Where we are in the rotation, counting from the epoch
+    let cycle: i64 = turns.iter().map(|s| s.dwell_seconds.max(1)).sum();
+    let start = now.timestamp() - now.timestamp().rem_euclid(cycle);
+    let mut at = now.timestamp().rem_euclid(cycle);
+    let mut offset = 0;
+    for slot in turns {
+        let dwell = slot.dwell_seconds.max(1);
+        if at < dwell {
+            let until = DateTime::<Utc>::from_timestamp(start + offset + dwell, 0)?;
+            return Some(Turn { slot, until });
+        }
+        at -= dwell;
+        offset += dwell;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, FixedClock};
+    use crate::playlist::{schedule, Condition, Content, Slot};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    fn slot(content: Content, dwell_seconds: i64, when: Condition, priority: i32) -> Slot {
+        Slot {
+            content,
+            dwell_seconds,
+            when,
+            priority,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let slots = [
+            slot(Content::Events, 20, Condition::Always, 0),
+            slot(Content::Clock, 10, Condition::Free, 0),
+            slot(Content::Announcements, 10, Condition::Always, 0),
+            // Takes over for as long as the room is busy
+            slot(Content::Agenda, 30, Condition::Busy, 5),
+        ];
+        let at = |s| FixedClock(Utc.with_ymd_and_hms(2020, 1, 1, 19, 0, s).unwrap());
+        let nothing_to_announce = |s: &Slot| s.content != Content::Announcements;
+        let pick = |clock: FixedClock, busy| {
+            schedule(&slots, busy, nothing_to_announce, clock.now()).map(|t| t.slot.content)
+        };
+
+        // The rotation is 30s long without announcements, and starts over
+        // on the minute
+        assert_eq!(pick(at(0), false), Some(Content::Events));
+        assert_eq!(pick(at(19), false), Some(Content::Events));
+        assert_eq!(pick(at(20), false), Some(Content::Clock));
+        assert_eq!(pick(at(30), false), Some(Content::Events));
+        let turn = schedule(&slots, false, nothing_to_announce, at(25).now()).unwrap();
+        assert_eq!(turn.until - at(25).now(), Duration::seconds(5));
+
+        // With announcements, they get a turn too
+        let turn = schedule(&slots, false, |_| true, at(35).now()).unwrap();
+        assert_eq!(turn.slot.content, Content::Announcements);
+
+        // The higher priority slot has it to itself while its condition
+        // holds
+        assert_eq!(pick(at(20), true), Some(Content::Agenda));
+
+        // And nothing that can go means nothing to show
+        assert_eq!(schedule(&slots[1..2], true, |_| true, at(0).now()), None);
+    }
+}
@@ -0,0 +1,408 @@
+// Content sources compiled to WebAssembly, for integrations that don't
+// belong in the proxy itself, like a door sensor or the print queue. A
+// plugin gets a fresh instance every time it's fetched, a fixed amount of
+// fuel and memory to do its work in, and no way to reach the outside world
+// besides asking us to fetch a URL from a host its config allows.
+//
+// A plugin exports:
+//   memory
+//   sidegrade_alloc(len: i32) -> i32
+//     Room for us to write `len` bytes into
+//   sidegrade_schema() -> i64
+//     A JSON Schema for its config
+//   sidegrade_items(config: i32, len: i32) -> i64
+//     Given its config as JSON, its items as JSON:
+//     [{"text": "Printer: 3 jobs", "flash": false}]
+//     `flash` is all there is in the way of render hints. Items go up on a
+//     ticker like any other source's, which doesn't take any others.
+//
+// and can import, from "sidegrade":
+//   fetch(url: i32, len: i32) -> i64
+//     GET a URL. The body comes back like any other string, or -1. Only
+//     the first few fetches each time around get made.
+//   log(message: i32, len: i32)
+//
+// Strings going back and forth are UTF-8, passed as a pointer and a length,
+// or packed into an i64 as (pointer << 32) | length.
+
+use anyhow::{anyhow, bail};
+use reqwest::redirect;
+use std::{fs, time};
+use url::Url;
+use wasmi::{
+    AsContext, AsContextMut, Caller, Config, Engine, Extern, Instance, Linker, Memory, Module,
+    Store, StoreLimits, StoreLimitsBuilder,
+};
+
+use crate::source::{Item, Source};
+
+// How much work a plugin gets to do each time it's fetched
+const FUEL: u64 = 50_000_000;
+// and how much memory it gets to do it in
+const MEMORY_BYTES: usize = 16 * 1024 * 1024;
+// The most we'll pass along from a fetch, or take back from a plugin
+const MAX_STRING_BYTES: usize = 256 * 1024;
+const FETCH_TIMEOUT_SECONDS: u64 = 10;
+const MAX_REDIRECTS: usize = 5;
+// Fuel doesn't burn while we're waiting on the network, so this is what
+// keeps a plugin from tying up the sources for minutes at a time
+const MAX_FETCHES: usize = 4;
+
+// Gets a URL for a plugin, given the hosts it's allowed to reach, which it
+// has to hold every redirect to as well
+pub type Fetcher = fn(&str, &[String]) -> anyhow::Result<String>;
+
+pub struct Plugin {
+    pub path: String,
+    pub config: serde_json::Value,
+    // Hosts the plugin may fetch from
+    pub allow: Vec<String>,
+    // Only the first this many items
+    pub limit: usize,
+    pub fetcher: Fetcher,
+}
+
+// What a plugin's instance can see of us
+struct Host {
+    limits: StoreLimits,
+    allow: Vec<String>,
+    fetcher: Fetcher,
+    // How many it's asked for so far
+    fetches: usize,
+}
+
+// Whether a plugin may fetch a URL: plain HTTP(S), to a host it's been
+// allowed
+fn allowed(url: &Url, allow: &[String]) -> bool {
+    matches!(url.scheme(), "http" | "https")
+        && url
+            .host_str()
+            .is_some_and(|host| allow.iter().any(|a| a.eq_ignore_ascii_case(host)))
+}
+
+pub fn http_get(url: &str, allow: &[String]) -> anyhow::Result<String> {
+    // A host that's allowed mustn't be able to bounce the plugin anywhere
+    // it isn't, like somewhere on the proxy's own network
+    let allow = allow.to_vec();
+    let redirects = redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("Too many redirects")
+        } else if allowed(attempt.url(), &allow) {
+            attempt.follow()
+        } else {
+            let error = format!("Plugin isn't allowed to be redirected to {}", attempt.url());
+            attempt.error(error)
+        }
+    });
+    let body = reqwest::blocking::Client::builder()
+        .timeout(time::Duration::from_secs(FETCH_TIMEOUT_SECONDS))
+        .redirect(redirects)
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    Ok(body)
+}
+
+fn memory(instance: &Instance, ctx: impl AsContext) -> anyhow::Result<Memory> {
+    instance
+        .get_memory(ctx, "memory")
+        .ok_or(anyhow!("Plugin doesn't export its memory"))
+}
+
+fn read_string(memory: Memory, ctx: impl AsContext, ptr: i32, len: i32) -> anyhow::Result<String> {
+    let len = usize::try_from(len)?;
+    if len > MAX_STRING_BYTES {
+        bail!("Plugin sent {} bytes, more than {}", len, MAX_STRING_BYTES);
+    }
+    let mut buf = vec![0; len];
+    memory
+        .read(ctx, u32::try_from(ptr)? as usize, &mut buf)
+        .map_err(|e| anyhow!("{}", e))?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn unpack(packed: i64) -> anyhow::Result<(i32, i32)> {
+    if packed < 0 {
+        bail!("Plugin came back empty-handed");
+    }
+    Ok(((packed >> 32) as i32, packed as u32 as i32))
+}
+
+fn pack(ptr: i32, len: usize) -> i64 {
+    ((ptr as u32 as i64) << 32) | len as i64
+}
+
+// Copy bytes into the plugin's memory, wherever it says to put them
+fn write_bytes(
+    alloc: Option<Extern>,
+    memory: Memory,
+    mut ctx: impl AsContextMut,
+    bytes: &[u8],
+) -> anyhow::Result<i32> {
+    let alloc = alloc
+        .and_then(Extern::into_func)
+        .ok_or(anyhow!("Plugin doesn't export sidegrade_alloc"))?
+        .typed::<i32, i32>(&ctx)?;
+    let ptr = alloc.call(&mut ctx, i32::try_from(bytes.len())?)?;
+    memory
+        .write(&mut ctx, u32::try_from(ptr)? as usize, bytes)
+        .map_err(|e| anyhow!("{}", e))?;
+    Ok(ptr)
+}
+
+// The fetch a plugin asked for, if its config lets it
+fn fetch(caller: &mut Caller<'_, Host>, ptr: i32, len: i32) -> anyhow::Result<i64> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or(anyhow!("Plugin doesn't export its memory"))?;
+    let url = read_string(memory, &caller, ptr, len)?;
+    if caller.data().fetches >= MAX_FETCHES {
+        bail!(
+            "Plugin already fetched {} URLs, skipping {}",
+            MAX_FETCHES,
+            url
+        );
+    }
+    caller.data_mut().fetches += 1;
+    if !allowed(&Url::parse(&url)?, &caller.data().allow) {
+        bail!("Plugin isn't allowed to fetch {}", url);
+    }
+    let body = (caller.data().fetcher)(&url, &caller.data().allow)?;
+    if body.len() > MAX_STRING_BYTES {
+        bail!("{} sent more than {} bytes", url, MAX_STRING_BYTES);
+    }
+    let alloc = caller.get_export("sidegrade_alloc");
+    let ptr = write_bytes(alloc, memory, caller, body.as_bytes())?;
+    Ok(pack(ptr, body.len()))
+}
+
+impl Plugin {
+    fn instantiate(&self) -> anyhow::Result<(Store<Host>, Instance)> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &fs::read(&self.path)?)?;
+
+        let host = Host {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MEMORY_BYTES)
+                .memories(1)
+                .instances(1)
+                .build(),
+            allow: self.allow.clone(),
+            fetcher: self.fetcher,
+            fetches: 0,
+        };
+        let mut store = Store::new(&engine, host);
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(FUEL).map_err(|e| anyhow!("{}", e))?;
+
+        let mut linker = <Linker<Host>>::new(&engine);
+        linker.func_wrap(
+            "sidegrade",
+            "fetch",
+            |mut caller: Caller<'_, Host>, ptr: i32, len: i32| -> i64 {
+                fetch(&mut caller, ptr, len).unwrap_or_else(|e| {
+                    println!("Plugin fetch failed: {}", e);
+                    -1
+                })
+            },
+        )?;
+        linker.func_wrap(
+            "sidegrade",
+            "log",
+            |caller: Caller<'_, Host>, ptr: i32, len: i32| {
+                let message = caller
+                    .get_export("memory")
+                    .and_then(Extern::into_memory)
+                    .ok_or(anyhow!("no memory"))
+                    .and_then(|memory| read_string(memory, &caller, ptr, len));
+                if let Ok(message) = message {
+                    println!("Plugin: {}", message);
+                }
+            },
+        )?;
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        Ok((store, instance))
+    }
+
+    // Hold the config up against the plugin's schema. Only the top level
+    // gets checked: that it's an object, and has what's required. No config
+    // at all counts as an empty one.
+    fn check_config(&self, store: &mut Store<Host>, instance: &Instance) -> anyhow::Result<()> {
+        let schema = instance.get_typed_func::<(), i64>(&*store, "sidegrade_schema")?;
+        let (ptr, len) = unpack(schema.call(&mut *store, ())?)?;
+        let schema: serde_json::Value =
+            serde_json::from_str(&read_string(memory(instance, &*store)?, &*store, ptr, len)?)?;
+
+        let empty = serde_json::Map::new();
+        let config = match &self.config {
+            serde_json::Value::Null => &empty,
+            config => config
+                .as_object()
+                .ok_or(anyhow!("Plugin config has to be an object"))?,
+        };
+        let required = schema["required"].as_array().cloned().unwrap_or_default();
+        for key in required.iter().filter_map(|k| k.as_str()) {
+            if !config.contains_key(key) {
+                bail!("Plugin config is missing {}", key);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Source for Plugin {
+    fn fetch(&self) -> anyhow::Result<Vec<Item>> {
+        let (mut store, instance) = self.instantiate()?;
+        self.check_config(&mut store, &instance)?;
+
+        let config = serde_json::to_vec(&self.config)?;
+        let memory = memory(&instance, &store)?;
+        let alloc = instance.get_export(&store, "sidegrade_alloc");
+        let ptr = write_bytes(alloc, memory, &mut store, &config)?;
+        let items = instance.get_typed_func::<(i32, i32), i64>(&store, "sidegrade_items")?;
+        let (ptr, len) = unpack(items.call(&mut store, (ptr, config.len() as i32))?)?;
+        let mut items: Vec<Item> = serde_json::from_str(&read_string(memory, &store, ptr, len)?)?;
+        items.truncate(self.limit);
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plugin::{allowed, http_get, Plugin, MAX_FETCHES};
+    use crate::source::{Item, Source};
+    use serde_json::json;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use url::Url;
+
+    // Fetches the print queue `fetches` times, and hands back whatever it
+    // said the last time
+    fn printer_queue(fetches: usize) -> String {
+        let schema = r#"{"required": ["printer"]}"#;
+        let url = "http://printer.local/queue";
+        format!(
+            r#"(module
+                (import "sidegrade" "fetch" (func $fetch (param i32 i32) (result i64)))
+                (memory (export "memory") 1)
+                (global $next (mut i32) (i32.const 1024))
+                (data (i32.const 0) "{}")
+                (data (i32.const 512) "{}")
+                (func (export "sidegrade_alloc") (param $len i32) (result i32)
+                    (local $at i32)
+                    (local.set $at (global.get $next))
+                    (global.set $next (i32.add (global.get $next) (local.get $len)))
+                    (local.get $at))
+                (func (export "sidegrade_schema") (result i64)
+                    (i64.const {}))
+                (func (export "sidegrade_items") (param i32 i32) (result i64)
+                    (local $n i32)
+                    (local $last i64)
+                    (loop $again
+                        (local.set $last (call $fetch (i32.const 512) (i32.const {})))
+                        (local.set $n (i32.add (local.get $n) (i32.const 1)))
+                        (br_if $again (i32.lt_u (local.get $n) (i32.const {}))))
+                    (local.get $last)))"#,
+            schema.replace('"', "\\\""),
+            url,
+            schema.len(),
+            url.len(),
+            fetches
+        )
+    }
+
+    fn plugin(wat: &str, name: &str, allow: &[&str], config: serde_json::Value) -> Plugin {
+        let path =
+            std::env::temp_dir().join(format!("sidegrade-{}-{}.wasm", std::process::id(), name));
+        fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        Plugin {
+            path: path.to_str().unwrap().to_string(),
+            config,
+            allow: allow.iter().map(|a| a.to_string()).collect(),
+            limit: 10,
+            fetcher: |url, _| match url {
+                "http://printer.local/queue" => {
+                    Ok(r#"[{"text": "Printer: 3 jobs", "flash": true}]"#.to_string())
+                }
+                _ => Err(anyhow::anyhow!("No such page")),
+            },
+        }
+    }
+
+    #[test]
+    fn test_plugin() {
+        let config = json!({"printer": "Lounge"});
+        let queue = plugin(
+            &printer_queue(1),
+            "queue",
+            &["printer.local"],
+            config.clone(),
+        );
+        assert_eq!(
+            queue.fetch().unwrap(),
+            [Item {
+                text: "Printer: 3 jobs".to_string(),
+                flash: true
+            }]
+        );
+
+        // Only the hosts it's allowed
+        let queue = plugin(&printer_queue(1), "queue", &["example.org"], config.clone());
+        assert!(queue.fetch().is_err());
+        // and only with the config it asks for
+        let queue = plugin(&printer_queue(1), "queue", &["printer.local"], json!({}));
+        assert!(queue.fetch().is_err());
+
+        // and only so many times each time around
+        let busy = printer_queue(MAX_FETCHES);
+        let queue = plugin(&busy, "busy", &["printer.local"], config.clone());
+        assert!(queue.fetch().is_ok());
+        let busier = printer_queue(MAX_FETCHES + 1);
+        let queue = plugin(&busier, "busier", &["printer.local"], config);
+        assert!(queue.fetch().is_err());
+
+        // Runs out of fuel
+        let spin = r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 0) "{}")
+            (func (export "sidegrade_alloc") (param i32) (result i32) (i32.const 64))
+            (func (export "sidegrade_schema") (result i64) (i64.const 2))
+            (func (export "sidegrade_items") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0)))"#;
+        let spin = plugin(spin, "spin", &[], json!({}));
+        assert!(spin.fetch().unwrap_err().to_string().contains("fuel"));
+
+        // and out of memory
+        let greedy = r#"(module (memory (export "memory") 1024))"#;
+        assert!(plugin(greedy, "greedy", &[], json!({})).fetch().is_err());
+    }
+
+    #[test]
+    fn test_allowed() {
+        let allow = ["printer.local".to_string(), "127.0.0.1".to_string()];
+        let url = |u| Url::parse(u).unwrap();
+        assert!(allowed(&url("http://printer.local/queue"), &allow));
+        assert!(!allowed(&url("http://example.org/"), &allow));
+        assert!(!allowed(&url("file:///etc/passwd"), &allow));
+        assert!(!allowed(&url("ftp://printer.local/queue"), &allow));
+
+        // An allowed host can't send it somewhere that isn't
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            let response = "HTTP/1.1 302 Found\r\nLocation: http://internal.example/secrets\r\nContent-Length: 0\r\n\r\n";
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        let error = http_get(&format!("http://127.0.0.1:{}/queue", port), &allow).unwrap_err();
+        assert!(format!("{:?}", error).contains("allowed to be redirected"));
+    }
+}
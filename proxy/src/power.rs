@@ -0,0 +1,143 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, NaiveTime, Utc, Weekday};
+use serde::Deserialize;
+
+pub use display_core::sign::Power;
+
+use crate::calendar::Timeline;
+use crate::config::LocationConfig;
+
+const EVERY_DAY: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+fn every_day() -> Vec<Weekday> {
+    EVERY_DAY.to_vec()
+}
+
+// A stretch of the week in the location's timezone, like 23:00 to 07:00
+// every night. `days` are the days it starts on. When `to` isn't after
+// `from`, it runs on into the next day, so 00:00 to 00:00 is all day.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Hours {
+    #[serde(default = "every_day")]
+    pub days: Vec<Weekday>,
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+}
+
+impl Hours {
+    pub fn contains(&self, local: NaiveDateTime) -> bool {
+        let (date, time) = (local.date(), local.time());
+        if self.from < self.to {
+            return self.days.contains(&date.weekday()) && self.from <= time && time < self.to;
+        }
+        // Overnight, so it's either the evening it started or the morning
+        // after
+        (self.days.contains(&date.weekday()) && time >= self.from)
+            || (self.days.contains(&date.weekday().pred()) && time < self.to)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PowerConfig {
+    // Backlight off, text still up
+    pub quiet_hours: Vec<Hours>,
+    // Display off altogether
+    pub closed: Vec<Hours>,
+    // Wake up this long before an event, even in quiet hours or when the
+    // room is meant to be closed
+    pub wake_minutes: i64,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        PowerConfig {
+            quiet_hours: vec![],
+            closed: vec![],
+            wake_minutes: 15,
+        }
+    }
+}
+
+// How lit a sign should be. Anything going on in the rooms it covers, or
+// about to, keeps it on regardless of the schedule.
+pub fn power(timelines: &[&Timeline], now: DateTime<Utc>, location: &LocationConfig) -> Power {
+    let config = &location.power;
+    let wake = now + Duration::try_minutes(config.wake_minutes).unwrap_or_default();
+    let busy = timelines.iter().any(|t| {
+        t.current(now).is_some()
+            || t.next(now)
+                .and_then(|e| e.start.date_time)
+                .is_some_and(|start| start <= wake)
+    });
+    if busy {
+        return Power::On;
+    }
+
+    let local = now.with_timezone(&location.timezone).naive_local();
+    if config.closed.iter().any(|h| h.contains(local)) {
+        Power::Off
+    } else if config.quiet_hours.iter().any(|h| h.contains(local)) {
+        Power::Dim
+    } else {
+        Power::On
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{event, Timeline};
+    use crate::config::LocationConfig;
+    use crate::power::{power, Hours, Power, PowerConfig};
+    use chrono::prelude::*;
+    use chrono_tz::UTC;
+
+    #[test]
+    fn test_power() {
+        let location = LocationConfig {
+            timezone: UTC,
+            power: serde_json::from_str::<PowerConfig>(
+                r#"{
+                    "quiet_hours": [{ "from": "22:00", "to": "08:00" }],
+                    "closed": [{ "days": ["Sat"], "from": "00:00", "to": "00:00" }]
+                }"#,
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let at = |d, h, m| Utc.with_ymd_and_hms(2020, 1, d, h, m, 0).unwrap();
+        // 2020-01-03 was a Friday
+        let late = Timeline {
+            events: vec![event("Smash", at(3, 23, 0), at(3, 23, 30))],
+        };
+        let state = |d, h, m| power(&[&late], at(d, h, m), &location);
+
+        assert_eq!(state(3, 12, 0), Power::On);
+        assert_eq!(state(3, 22, 0), Power::Dim);
+        // Awake for the event, and while it's on
+        assert_eq!(state(3, 22, 45), Power::On);
+        assert_eq!(state(3, 23, 15), Power::On);
+        // Saturday's closed all day, quiet hours or not
+        assert_eq!(state(4, 0, 0), Power::Off);
+        assert_eq!(state(4, 12, 0), Power::Off);
+        // and Sunday morning is back to quiet hours
+        assert_eq!(state(5, 7, 0), Power::Dim);
+        assert_eq!(state(5, 8, 0), Power::On);
+
+        let weekdays = Hours {
+            days: vec![Weekday::Mon, Weekday::Fri],
+            from: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            to: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        };
+        assert!(weekdays.contains(at(3, 9, 0).naive_utc()));
+        assert!(!weekdays.contains(at(3, 17, 0).naive_utc()));
+        assert!(!weekdays.contains(at(4, 12, 0).naive_utc()));
+    }
+}
@@ -0,0 +1,196 @@
+// Draw signs for a browser, so they can be checked on without walking
+// around the house. Panels get the same text a device would, and are
+// drawn dot for dot with the ROM's font.
+
+use chrono::Duration;
+use display_core::{
+    font::{self, glyph, lit},
+    frame,
+};
+
+use crate::render::Geometry;
+
+// How often a preview reloads when nothing on it is about to change.
+// Matches how often a sign asks on its own.
+const DEFAULT_REFRESH: i64 = 10;
+
+// One sign on a preview page
+pub struct Panel {
+    pub name: String,
+    // Where clicking on the name goes, if anywhere
+    pub link: Option<String>,
+    // Exactly what the device would be sent
+    pub text: String,
+    pub geometry: Geometry,
+    pub refresh: Option<Duration>,
+}
+
+// A whole page of panels, which reloads when the soonest of them is due
+// to change
+pub fn page(title: &str, panels: &[Panel]) -> String {
+    let refresh = panels
+        .iter()
+        .filter_map(|p| p.refresh)
+        .min()
+        .map(|r| r.num_seconds())
+        .unwrap_or(DEFAULT_REFRESH)
+        .clamp(1, DEFAULT_REFRESH);
+
+    let mut html = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="{}">
+<title>{}</title>
+<style>
+body {{ background: #222; color: #eee; font-family: sans-serif; }}
+.panel {{ display: inline-block; margin: 1em; vertical-align: top; }}
+.panel a {{ color: #eee; }}
+.panel svg {{ display: block; width: 100%; }}
+</style>
+</head>
+<body>
+"#,
+        refresh,
+        escape(title)
+    );
+    for (idx, panel) in panels.iter().enumerate() {
+        let name = match &panel.link {
+            Some(link) => format!(r#"<a href="{}">{}</a>"#, escape(link), escape(&panel.name)),
+            None => escape(&panel.name),
+        };
+        html.push_str(&format!(
+            "<div class=\"panel\" style=\"width: {}em\">\n<h3>{}</h3>\n{}</div>\n",
+            panel.geometry.cols * 2,
+            name,
+            lcd(&format!("p{}", idx), &panel.text, panel.geometry)
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// Each cell is 5x8 dots, with a dot's worth of space between cells and a
+// border of two dots around the whole panel
+const CELL_WIDTH: usize = font::WIDTH + 1;
+const CELL_HEIGHT: usize = font::HEIGHT + 1;
+const BORDER: usize = 2;
+
+const BACKLIGHT: &str = "#1d3fbb";
+const UNLIT: &str = "#2a50d8";
+const LIT: &str = "#f0f4ff";
+
+// An SVG of a panel. Rows that are too long to fit are cut off where the
+// sign would start scrolling them. `id` keeps glyph definitions from
+// clashing with other panels on the same page.
+fn lcd(id: &str, text: &str, geometry: Geometry) -> String {
+    let rows = frame::compose(&frame::lines(text), geometry.rows);
+    let width = geometry.cols * CELL_WIDTH - 1 + BORDER * 2;
+    let height = geometry.rows * CELL_HEIGHT - 1 + BORDER * 2;
+
+    // Each glyph is drawn once, then placed wherever it's used
+    let mut used: Vec<u8> = rows
+        .iter()
+        .flat_map(|r| r.iter().take(geometry.cols).cloned())
+        .collect();
+    used.sort();
+    used.dedup();
+    let mut defs = String::new();
+    for b in used {
+        let g = glyph(b);
+        defs.push_str(&format!(r#"<g id="{}-{:02x}">"#, id, b));
+        for y in 0..font::HEIGHT {
+            for x in 0..font::WIDTH {
+                if lit(&g, x, y) {
+                    defs.push_str(&format!(
+                        r#"<rect x="{}" y="{}" width="0.9" height="0.9"/>"#,
+                        x, y
+                    ));
+                }
+            }
+        }
+        defs.push_str("</g>");
+    }
+
+    let mut cells = String::new();
+    for (r, row) in rows.iter().enumerate() {
+        for c in 0..geometry.cols {
+            let (x, y) = (BORDER + c * CELL_WIDTH, BORDER + r * CELL_HEIGHT);
+            cells.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                x,
+                y,
+                font::WIDTH,
+                font::HEIGHT,
+                UNLIT
+            ));
+            if let Some(b) = row.get(c) {
+                cells.push_str(&format!(
+                    r##"<use href="#{}-{:02x}" x="{}" y="{}"/>"##,
+                    id, b, x, y
+                ));
+            }
+        }
+    }
+
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}">
+<defs>{defs}</defs>
+<rect width="{w}" height="{h}" fill="{bg}"/>
+<g fill="{lit}">{cells}</g>
+</svg>
+"#,
+        w = width,
+        h = height,
+        defs = defs,
+        bg = BACKLIGHT,
+        lit = LIT,
+        cells = cells
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::preview::{page, Panel};
+    use crate::render::Geometry;
+
+    #[test]
+    fn test_page() {
+        let panels = [
+            Panel {
+                name: "Lounge <3".to_string(),
+                link: Some("/locations/lounge/preview".to_string()),
+                text: "Smash\nIn 5m".to_string(),
+                geometry: Geometry::default(),
+                refresh: Some(Duration::try_seconds(3).unwrap()),
+            },
+            Panel {
+                name: "Library".to_string(),
+                link: None,
+                text: "●".to_string(),
+                geometry: Geometry::new(2, 16).unwrap(),
+                refresh: None,
+            },
+        ];
+        let html = page("Wall", &panels);
+
+        assert!(html.contains(r#"content="3""#));
+        assert!(html.contains("Lounge &lt;3"));
+        // A cell for every character on each panel
+        assert_eq!(html.matches(r##"fill="#2a50d8""##).count(), 4 * 20 + 2 * 16);
+        // Glyphs are defined once per panel, and used once per character
+        assert_eq!(html.matches(r#"<g id="p0-53">"#).count(), 1);
+        assert_eq!(html.matches("#p0-6d").count(), 2);
+        assert_eq!(html.matches(r##"href="#p1-01""##).count(), 1);
+    }
+}
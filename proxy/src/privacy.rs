@@ -0,0 +1,133 @@
+use serde::Deserialize;
+
+use crate::calendar::{Event, Timeline};
+use crate::config::LocationConfig;
+
+// What a private event is called on the sign
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Placeholder {
+    #[default]
+    Reserved,
+    Busy,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub placeholder: Placeholder,
+    // Keep every title here to ourselves, for rooms like study rooms
+    pub all: bool,
+    // Words that make an event private wherever they are in its title,
+    // like "study group"
+    pub keywords: Vec<String>,
+    // When set, only events organized from these calendars have their
+    // titles shown
+    pub calendars: Option<Vec<String>>,
+}
+
+impl PrivacyConfig {
+    pub fn is_private(&self, event: &Event) -> bool {
+        if self.all || event.is_private() {
+            return true;
+        }
+        let title = event.summary.to_lowercase();
+        if self
+            .keywords
+            .iter()
+            .any(|k| title.contains(&k.to_lowercase()))
+        {
+            return true;
+        }
+        match &self.calendars {
+            Some(calendars) => !event
+                .organizer
+                .as_ref()
+                .and_then(|o| o.email.as_ref())
+                .is_some_and(|email| calendars.iter().any(|c| c.eq_ignore_ascii_case(email))),
+            None => false,
+        }
+    }
+}
+
+// The timeline as a sign here should see it. Private events keep their
+// times, so the room still shows as taken, but lose their titles and
+// anything the organizer wrote for the sign.
+pub fn redact(timeline: &Timeline, location: &LocationConfig) -> Timeline {
+    let catalog = location.locale.catalog();
+    let placeholder = match location.privacy.placeholder {
+        Placeholder::Reserved => catalog.reserved,
+        Placeholder::Busy => catalog.busy,
+    };
+    let events = timeline
+        .events
+        .iter()
+        .map(|e| match location.privacy.is_private(e) {
+            true => Event {
+                summary: placeholder.to_string(),
+                description: None,
+                extended_properties: None,
+                ..e.clone()
+            },
+            false => e.clone(),
+        })
+        .collect();
+    Timeline { events }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{at, event, Event, Timeline};
+    use crate::config::LocationConfig;
+    use crate::privacy::{redact, Placeholder, PrivacyConfig};
+
+    fn booked(summary: &str, organizer: &str, visibility: &str) -> Event {
+        event(summary, at(18, 0), at(19, 0))
+            .with_description("sign: Secret")
+            .with_location("Library")
+            .with_visibility(visibility)
+            .with_organizer(organizer)
+    }
+
+    #[test]
+    fn test_redact() {
+        let timeline = Timeline {
+            events: vec![
+                booked("Board Games", "house@example.org", "default"),
+                booked("Alex's thing", "house@example.org", "private"),
+                booked("CS Study Group", "house@example.org", "public"),
+                booked("Movie Night", "alex@example.org", "default"),
+            ],
+        };
+        let mut location = LocationConfig {
+            privacy: PrivacyConfig {
+                keywords: vec!["study group".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let titles = |location: &LocationConfig| -> Vec<String> {
+            redact(&timeline, location)
+                .events
+                .iter()
+                .map(|e| e.title())
+                .collect()
+        };
+
+        assert_eq!(
+            titles(&location),
+            ["Secret", "Reserved", "Reserved", "Secret"]
+        );
+
+        location.privacy.calendars = Some(vec!["House@example.org".to_string()]);
+        location.privacy.placeholder = Placeholder::Busy;
+        assert_eq!(titles(&location), ["Secret", "Busy", "Busy", "Busy"]);
+
+        // Times are left alone
+        let redacted = redact(&timeline, &location);
+        assert_eq!(
+            redacted.events[1].start.date_time,
+            timeline.events[1].start.date_time
+        );
+    }
+}
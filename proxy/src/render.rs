@@ -0,0 +1,714 @@
+use chrono::{DateTime, Duration, Utc};
+use display_core::charset;
+use serde::Deserialize;
+
+use crate::calendar::{Event, Timeline};
+use crate::config::LocationConfig;
+use crate::countdown;
+use crate::locale::Catalog;
+use crate::power::{self, Power};
+use crate::urgency::{self, Urgency};
+
+// What a sign does with its rows
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    // The next event, and how long until it starts (or ends)
+    #[default]
+    Countdown,
+    // What's going on now, and what's on next
+    NowNext,
+    // The rest of today's events, a page at a time
+    Agenda,
+    // Just the time and date, across the whole panel
+    Clock,
+}
+
+// The size of a device's panel, in characters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Default for Geometry {
+    // The 2004 the signs were built around
+    fn default() -> Self {
+        Geometry { rows: 4, cols: 20 }
+    }
+}
+
+impl Geometry {
+    // HD44780 panels come in 1 to 4 rows of up to 40 characters
+    pub fn new(rows: usize, cols: usize) -> Option<Self> {
+        match (1..=4).contains(&rows) && (8..=40).contains(&cols) {
+            true => Some(Geometry { rows, cols }),
+            false => None,
+        }
+    }
+
+    // Panels with room for it get the clock along the bottom
+    pub fn has_footer(&self) -> bool {
+        self.rows > 2
+    }
+
+    // How many rows are left for a layout to use
+    pub fn content_rows(&self) -> usize {
+        match self.has_footer() {
+            true => self.rows - 1,
+            false => self.rows,
+        }
+    }
+}
+
+// What a sign should be showing, before it gets laid out for the display
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Screen {
+    // Rows to show above the clock
+    pub rows: Vec<String>,
+    // "2/3", shown next to the clock when there's more than one page
+    pub page: Option<String>,
+    // How long until this screen goes stale on its own
+    pub refresh: Option<Duration>,
+    // What state the room's schedule is in, and whether to blink about it
+    pub urgency: Urgency,
+    pub flash: bool,
+    // Words for that state, shown where the page number would go
+    pub label: Option<String>,
+    // Whether the panel should be lit at all
+    pub power: Power,
+    // An emergency broadcast, which devices show without waiting
+    pub emergency: bool,
+    // Rows for the whole panel, with no clock along the bottom
+    pub bare: bool,
+}
+
+impl Screen {
+    pub fn from_rows(rows: Vec<String>) -> Self {
+        Screen {
+            rows,
+            ..Default::default()
+        }
+    }
+
+    // Show off the state the room is in, and check back when it changes
+    fn present(
+        &mut self,
+        urgency: Urgency,
+        change: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+        location: &LocationConfig,
+    ) {
+        let presentation = location.urgency.presentation(urgency);
+        self.urgency = urgency;
+        self.flash = presentation.flash;
+        if presentation.label {
+            self.label = urgency::label(urgency, location).map(String::from);
+        }
+        if let Some(change) = change {
+            self.refresh_at(change, now);
+        }
+    }
+
+    // Make sure the screen gets looked at again by `at`
+    pub fn refresh_at(&mut self, at: DateTime<Utc>, now: DateTime<Utc>) {
+        if at > now {
+            let until = at - now;
+            self.refresh = Some(self.refresh.map_or(until, |r| r.min(until)));
+        }
+    }
+
+    // Lay rows out on the panel, with the clock in the bottom right if
+    // there's room for it
+    pub fn to_text(
+        &self,
+        now: DateTime<Utc>,
+        location: &LocationConfig,
+        geometry: Geometry,
+    ) -> String {
+        let footer = geometry.has_footer() && !self.bare;
+        let mut rows = self.rows.clone();
+        rows.resize(
+            match footer {
+                true => geometry.content_rows(),
+                false => geometry.rows,
+            },
+            String::new(),
+        );
+
+        if footer {
+            let now = now.with_timezone(&location.timezone);
+            // The brackets go first on panels too narrow for them, like
+            // "[10:05pm]" on 8 columns
+            let time = location.clock.time(&now);
+            let time_text = match time.chars().count() + 2 <= geometry.cols {
+                true => format!("[{}]", time),
+                false => time.chars().take(geometry.cols).collect(),
+            };
+            // Measured the way the panel will draw it, one character to a
+            // cell, and cut short so the clock always fits
+            let page = self.label.clone().or(self.page.clone()).unwrap_or_default();
+            let room = geometry.cols.saturating_sub(time_text.chars().count());
+            let page: String = charset::lcd_safe(&page).chars().take(room).collect();
+            let footer = format!(
+                "{}{: >width$}",
+                page,
+                time_text,
+                width = geometry.cols - page.chars().count()
+            );
+            rows.push(footer);
+        } else if let (Some(label), Some(last), false) = (&self.label, rows.last_mut(), self.bare) {
+            // No footer to put it in, so it takes over the last row
+            *last = label.clone();
+        }
+
+        // Keep everything to what the display can actually draw
+        charset::lcd_safe(&rows.join("\n"))
+    }
+}
+
+pub fn render(
+    layout: Layout,
+    timeline: &Timeline,
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    geometry: Geometry,
+) -> Screen {
+    let rows = geometry.content_rows();
+    let mut screen = match layout {
+        Layout::Countdown => Screen::from_rows(countdown(timeline, now, location)),
+        Layout::NowNext => Screen::from_rows(now_next(timeline, now, location, rows)),
+        Layout::Agenda => agenda(timeline, now, location, rows),
+        Layout::Clock => clock(now, location, geometry),
+    };
+    let (urgency, change) = urgency::classify(timeline, now, &location.urgency);
+    screen.present(urgency, change, now, location);
+    screen.power = power::power(&[timeline], now, location);
+    screen
+}
+
+fn countdown(timeline: &Timeline, now: DateTime<Utc>, location: &LocationConfig) -> Vec<String> {
+    // The first event that isn't over yet, unless one's been pinned. All-day
+    // events have no end time, and stay at the front.
+    let upcoming: Vec<&Event> = timeline
+        .events
+        .iter()
+        .filter(|e| e.end.date_time.is_none_or(|end| end > now))
+        .collect();
+    let event = upcoming.iter().find(|e| e.sign().pin).or(upcoming.first());
+    let text = match event {
+        Some(e) => e.format_2004(now, location),
+        None => location.locale.catalog().no_events.to_string(),
+    };
+    text.lines().map(String::from).collect()
+}
+
+// Now: Board Games (12m Left)
+// Busy until 21:00
+// Next: Movie Night at 21:30
+fn now_next(
+    timeline: &Timeline,
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    max_rows: usize,
+) -> Vec<String> {
+    let catalog = location.locale.catalog();
+    let mut rows = vec![];
+    let mut folded = false;
+
+    match timeline.current(now) {
+        Some(e) => {
+            let mut current = e.title();
+            if let Some(end) = e.end.date_time {
+                current = format!(
+                    "{} ({})",
+                    current,
+                    countdown::time_left(end - now, location)
+                );
+            }
+            rows.push(Catalog::fill(catalog.now, &current));
+
+            // Back-to-back bookings get folded into one line, if there's
+            // room to say so and still show what's next
+            if let Some(until) = timeline.busy_until(now).filter(|_| max_rows > 2) {
+                if e.end.date_time.is_some_and(|end| until > end) {
+                    let until = until.with_timezone(&location.timezone);
+                    rows.push(Catalog::fill(
+                        catalog.busy_until,
+                        &location.clock.time(&until),
+                    ));
+                    folded = true;
+                }
+            }
+        }
+        None => rows.push(Catalog::fill(catalog.now, catalog.free)),
+    }
+
+    let next = match folded {
+        true => timeline.next_after_busy(now),
+        false => timeline.next(now),
+    };
+    match next {
+        Some(e) => {
+            let start = e.start.date_time.unwrap().with_timezone(&location.timezone);
+            let when =
+                match start.date_naive() == now.with_timezone(&location.timezone).date_naive() {
+                    true => location.clock.time(&start),
+                    false => location.locale.weekday_time(&start, location.clock),
+                };
+            rows.push(Catalog::fill2(catalog.next, &e.title(), &when));
+        }
+        None => rows.push(catalog.no_events.to_string()),
+    }
+
+    rows
+}
+
+// 19:00 Board Games
+// 21:30 Movie Night
+fn agenda(
+    timeline: &Timeline,
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    per_page: usize,
+) -> Screen {
+    let rows: Vec<String> = timeline
+        .today(now, location.timezone)
+        .iter()
+        .map(|e| {
+            let start = e.start.date_time.unwrap().with_timezone(&location.timezone);
+            format!("{} {}", location.clock.time(&start), e.title())
+        })
+        .collect();
+
+    if rows.is_empty() {
+        return Screen::from_rows(vec![location.locale.catalog().no_events.to_string()]);
+    }
+    paged(rows, per_page, now, location.page_seconds)
+}
+
+//        19:05
+//     Wed Mar 14
+fn clock(now: DateTime<Utc>, location: &LocationConfig, geometry: Geometry) -> Screen {
+    let local = now.with_timezone(&location.timezone);
+    let time = location.clock.time(&local);
+    let date = location.locale.date(&local);
+    let centred = |text: &str| {
+        let width = charset::lcd_safe(text).chars().count();
+        format!(
+            "{}{}",
+            " ".repeat(geometry.cols.saturating_sub(width) / 2),
+            text
+        )
+    };
+    let mut rows = match geometry.rows {
+        1 => vec![format!("{} {}", time, date)],
+        _ => vec![centred(&time), centred(&date)],
+    };
+    // Down the middle of the panel
+    for _ in 0..(geometry.rows.saturating_sub(rows.len()) / 2) {
+        rows.insert(0, String::new());
+    }
+
+    // Back when the minute turns over
+    let next_minute =
+        DateTime::<Utc>::from_timestamp((now.timestamp().div_euclid(60) + 1) * 60, 0).unwrap();
+    Screen {
+        rows,
+        refresh: Some(next_minute - now),
+        bare: true,
+        ..Default::default()
+    }
+}
+
+// ● Lounge: Board Games
+// ○ Library: Free
+pub fn group(
+    members: &[(String, Timeline)],
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    geometry: Geometry,
+) -> Screen {
+    let catalog = location.locale.catalog();
+    let rows = members
+        .iter()
+        .map(|(name, timeline)| match timeline.current(now) {
+            Some(e) => format!("{}{}: {}", charset::GLYPH_BUSY, name, e.title()),
+            None => format!("{}{}: {}", charset::GLYPH_FREE, name, catalog.free),
+        })
+        .collect();
+    let mut screen = paged(rows, geometry.content_rows(), now, location.page_seconds);
+    let timelines: Vec<&Timeline> = members.iter().map(|(_, t)| t).collect();
+    screen.power = power::power(&timelines, now, location);
+    screen
+}
+
+// Split rows up into pages that flip every page_seconds. Which page is up
+// is worked out from the clock, so every sign showing the same thing
+// stays in step.
+pub fn paged(rows: Vec<String>, per_page: usize, now: DateTime<Utc>, page_seconds: i64) -> Screen {
+    if rows.len() <= per_page {
+        return Screen::from_rows(rows);
+    }
+
+    let pages = rows.len().div_ceil(per_page) as i64;
+    let page_seconds = page_seconds.max(1);
+    let tick = now.timestamp().div_euclid(page_seconds);
+    let page = tick.rem_euclid(pages);
+    let next_flip = DateTime::<Utc>::from_timestamp((tick + 1) * page_seconds, 0).unwrap();
+
+    Screen {
+        rows: rows
+            .into_iter()
+            .skip(page as usize * per_page)
+            .take(per_page)
+            .collect(),
+        page: Some(format!("{}/{}", page + 1, pages)),
+        refresh: Some(next_flip - now),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{at, event, Timeline};
+    use crate::clock::{Clock, FixedClock};
+    use crate::config::LocationConfig;
+    use crate::countdown::{CountdownConfig, DurationStyle};
+    use crate::locale::ClockFormat;
+    use crate::render::{group, render, Geometry, Layout, Screen};
+    use crate::urgency::Urgency;
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use chrono_tz::UTC;
+
+    #[test]
+    fn test_countdown_at() {
+        let location = LocationConfig::default();
+        let timeline = Timeline {
+            events: vec![
+                event("Smash", at(19, 0), at(20, 0)),
+                event("Movie", at(20, 30), at(22, 0)),
+            ],
+        };
+        let at = |h, m| FixedClock(Utc.with_ymd_and_hms(2020, 1, 1, h, m, 0).unwrap());
+        let rows = |clock: FixedClock| {
+            render(
+                Layout::Countdown,
+                &timeline,
+                clock.now(),
+                &location,
+                Geometry::default(),
+            )
+            .rows
+        };
+
+        assert_eq!(rows(at(18, 45)), ["Smash", "In 00:15:00"]);
+        assert_eq!(rows(at(19, 30)), ["Smash", "00:30:00 Left"]);
+        // Once it's over, on to the next one
+        assert_eq!(rows(at(20, 0)), ["Movie", "In 00:30:00"]);
+
+        // Right as it starts, the sign blinks and says so
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 19, 0, 20).unwrap();
+        let screen = render(
+            Layout::Countdown,
+            &timeline,
+            now,
+            &location,
+            Geometry::default(),
+        );
+        assert!(screen.flash);
+        assert_eq!(screen.urgency, Urgency::StartingNow);
+        assert_eq!(screen.refresh, Some(Duration::seconds(40)));
+        let text = screen.to_text(now, &location, Geometry::default());
+        assert_eq!(text.lines().last(), Some("STARTING NOW [14:00]"));
+        let two_rows = Geometry::new(2, 16).unwrap();
+        assert_eq!(
+            screen.to_text(now, &location, two_rows),
+            "Smash\nSTARTING NOW"
+        );
+    }
+
+    #[test]
+    fn test_sign_options() {
+        let location = LocationConfig::default();
+        let smash = event(
+            "Super Smash Bros. Ultimate Tournament",
+            at(19, 0),
+            at(20, 0),
+        )
+        .with_description("sign: Smash Night\nsign-line: Bring a controller");
+        let hackathon = event("Hackathon", at(22, 0), at(23, 0)).with_description("sign-pin");
+        let mut timeline = Timeline {
+            events: vec![smash, hackathon],
+        };
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 45, 0).unwrap();
+        let rows = |timeline: &Timeline, geometry| {
+            render(Layout::Countdown, timeline, now, &location, geometry).rows
+        };
+
+        // The pinned event jumps the queue
+        assert_eq!(
+            rows(&timeline, Geometry::default()),
+            ["Hackathon", "In 03:15:00"]
+        );
+
+        timeline.events[1].description = None;
+        assert_eq!(
+            rows(&timeline, Geometry::default()),
+            ["Smash Night", "In 00:15:00", "Bring a controller"]
+        );
+        let screen = render(
+            Layout::Countdown,
+            &timeline,
+            now,
+            &location,
+            Geometry::new(2, 16).unwrap(),
+        );
+        assert_eq!(
+            screen.to_text(now, &location, Geometry::new(2, 16).unwrap()),
+            "Smash Night\nIn 00:15:00"
+        );
+    }
+
+    #[test]
+    fn test_now_next() {
+        let location = LocationConfig {
+            timezone: UTC,
+            countdown: CountdownConfig {
+                style: DurationStyle::Compact,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let timeline = Timeline {
+            events: vec![
+                event("Board Games", at(18, 0), at(19, 0)),
+                event("Smash", at(19, 0), at(21, 0)),
+                event("Movie Night", at(21, 30), at(23, 0)),
+            ],
+        };
+
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 17, 0, 0).unwrap();
+        assert_eq!(
+            render(
+                Layout::NowNext,
+                &timeline,
+                now,
+                &location,
+                Geometry::default()
+            )
+            .rows,
+            vec!["Now: Free", "Next: Board Games at 18:00"]
+        );
+
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 48, 0).unwrap();
+        assert_eq!(
+            render(
+                Layout::NowNext,
+                &timeline,
+                now,
+                &location,
+                Geometry::default()
+            )
+            .rows,
+            vec![
+                "Now: Board Games (12m Left)",
+                "Busy until 21:00",
+                "Next: Movie Night at 21:30"
+            ]
+        );
+
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 22, 0, 0).unwrap();
+        assert_eq!(
+            render(
+                Layout::NowNext,
+                &timeline,
+                now,
+                &location,
+                Geometry::default()
+            )
+            .rows,
+            vec!["Now: Movie Night (1h 0m Left)", "No upcoming events."]
+        );
+    }
+
+    #[test]
+    fn test_agenda_pages() {
+        let location = LocationConfig {
+            timezone: UTC,
+            page_seconds: 10,
+            ..Default::default()
+        };
+        let timeline = Timeline {
+            events: vec![
+                event("Board Games", at(18, 0), at(19, 0)),
+                event("Smash", at(19, 0), at(21, 0)),
+                event("Movie Night", at(21, 30), at(23, 0)),
+                event("Late Night", at(23, 0), at(23, 30)),
+            ],
+        };
+
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 30, 0).unwrap();
+        let screen = render(
+            Layout::Agenda,
+            &timeline,
+            now,
+            &location,
+            Geometry::default(),
+        );
+        assert_eq!(
+            screen.rows,
+            vec!["18:00 Board Games", "19:00 Smash", "21:30 Movie Night"]
+        );
+        assert_eq!(screen.page.as_deref(), Some("1/2"));
+        assert_eq!(screen.refresh, Some(Duration::seconds(10)));
+
+        let now = now + Duration::seconds(14);
+        let screen = render(
+            Layout::Agenda,
+            &timeline,
+            now,
+            &location,
+            Geometry::default(),
+        );
+        assert_eq!(screen.rows, vec!["23:00 Late Night"]);
+        assert_eq!(screen.page.as_deref(), Some("2/2"));
+        assert_eq!(screen.refresh, Some(Duration::seconds(6)));
+        assert_eq!(
+            screen.to_text(now, &location, Geometry::default()),
+            "23:00 Late Night\n\n\n2/2          [18:30]"
+        );
+
+        // Once it fits on one page, no more paging
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 20, 0, 0).unwrap();
+        let screen = render(
+            Layout::Agenda,
+            &timeline,
+            now,
+            &location,
+            Geometry::default(),
+        );
+        assert_eq!(
+            screen.rows,
+            vec!["19:00 Smash", "21:30 Movie Night", "23:00 Late Night"]
+        );
+        assert_eq!(screen.page, None);
+        // Only needs to check back once Smash is nearly over
+        assert_eq!(screen.refresh, Some(Duration::minutes(55)));
+    }
+
+    #[test]
+    fn test_clock() {
+        let location = LocationConfig {
+            timezone: UTC,
+            ..Default::default()
+        };
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 19, 5, 45).unwrap();
+        let screen = render(
+            Layout::Clock,
+            &Timeline { events: vec![] },
+            now,
+            &location,
+            Geometry::default(),
+        );
+        assert_eq!(screen.refresh, Some(Duration::seconds(15)));
+        // No clock along the bottom, since it's already a clock
+        assert_eq!(
+            screen.to_text(now, &location, Geometry::default()),
+            "\n       19:05\n     Wed Jan 1\n"
+        );
+    }
+
+    #[test]
+    fn test_group() {
+        let location = LocationConfig {
+            page_seconds: 10,
+            ..Default::default()
+        };
+        let members = vec![
+            (
+                "Lounge".to_string(),
+                Timeline {
+                    events: vec![event("Board Games", at(18, 0), at(19, 0))],
+                },
+            ),
+            ("Library".to_string(), Timeline::default()),
+        ];
+
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 30, 0).unwrap();
+        assert_eq!(
+            group(&members, now, &location, Geometry::default()).rows,
+            vec!["●Lounge: Board Games", "○Library: Free"]
+        );
+
+        // More rooms than rows get paged
+        let screen = group(&members, now, &location, Geometry::new(1, 16).unwrap());
+        assert_eq!(screen.rows, vec!["●Lounge: Board Games"]);
+        assert_eq!(screen.page.as_deref(), Some("1/2"));
+    }
+
+    #[test]
+    fn test_geometries() {
+        let location = LocationConfig {
+            timezone: UTC,
+            ..Default::default()
+        };
+        let timeline = Timeline {
+            events: vec![
+                event("Board Games", at(18, 0), at(19, 0)),
+                event("Smash", at(19, 0), at(21, 0)),
+            ],
+        };
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 48, 0).unwrap();
+
+        // No room for the clock on two rows, or for the busy line
+        let g = Geometry::new(2, 16).unwrap();
+        let screen = render(Layout::NowNext, &timeline, now, &location, g);
+        assert_eq!(
+            screen.to_text(now, &location, g),
+            "Now: Board Games (00:12:00 Left)\nNext: Smash at 19:00"
+        );
+
+        let g = Geometry::new(4, 40).unwrap();
+        let screen = render(Layout::Countdown, &timeline, now, &location, g);
+        assert_eq!(
+            screen.to_text(now, &location, g).lines().last().unwrap(),
+            format!("{: >40}", "[18:48]")
+        );
+
+        // Labels that aren't ASCII, or are too long, still leave the clock
+        // where it belongs
+        let mut screen = Screen::from_rows(vec![]);
+        screen.label = Some("ZACZYNA SIĘ".to_string());
+        let g = Geometry::default();
+        assert_eq!(
+            screen.to_text(now, &location, g).lines().last().unwrap(),
+            "ZACZYNA SIE  [18:48]"
+        );
+        screen.label = Some("Occupé jusqu'à 19:00".to_string());
+        assert_eq!(
+            screen.to_text(now, &location, g).lines().last().unwrap(),
+            "Occupe jusqu'[18:48]"
+        );
+
+        // The narrowest panels still have room for the time, if not the
+        // brackets around it
+        let twelve_hour = LocationConfig {
+            clock: ClockFormat::H12,
+            ..location.clone()
+        };
+        let screen = Screen::from_rows(vec![]);
+        let g = Geometry::new(4, 8).unwrap();
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 22, 5, 0).unwrap();
+        let text = screen.to_text(now, &twelve_hour, g);
+        assert_eq!(text.lines().last().unwrap(), " 10:05pm");
+        assert_eq!(
+            screen.to_text(now, &location, g).lines().last().unwrap(),
+            " [22:05]"
+        );
+
+        assert!(Geometry::new(5, 20).is_none());
+        assert!(Geometry::new(2, 80).is_none());
+    }
+}
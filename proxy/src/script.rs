@@ -0,0 +1,273 @@
+// Rules for a location's sign that are too particular to be settings, kept
+// in a Rhai script next to the location config. The script gets the room's
+// events and the time, and hands back rows for the sign, the name of a
+// layout to use instead, or nothing to leave the sign alone. Scripts are
+// picked up again whenever they change on disk, can't touch the filesystem
+// or the network, and get cut off if they take too long.
+//
+// let next = events.filter(|e| e.start > now.timestamp);
+// if now.hour >= 23 { ["Quiet hours", "Goodnight!"] }
+// else if next.is_empty() { "clock" }
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use rhai::{module_resolvers::DummyModuleResolver, Array, Dynamic, Engine, Map, Scope, AST};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+use crate::calendar::Timeline;
+use crate::config::LocationConfig;
+use crate::render::{Geometry, Layout};
+
+// How long a script gets before it's stopped
+const TIME_LIMIT_MS: u128 = 50;
+// And how much it gets to do, so a busy loop can't get far either way
+const MAX_OPERATIONS: u64 = 200_000;
+
+// What a script wants the sign to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Rows(Vec<String>),
+    Layout(Layout),
+}
+
+// A script as it was the last time it was read. Ones that don't compile are
+// remembered too, so the error is only logged once per change.
+struct Compiled {
+    modified: Option<SystemTime>,
+    len: u64,
+    ast: Option<AST>,
+}
+
+// Every script that's been run, compiled. The proxy keeps one of these
+// for all its signs.
+#[derive(Default)]
+pub struct Scripts {
+    compiled: Mutex<HashMap<PathBuf, Compiled>>,
+}
+
+// An engine with everything that could reach outside the script, or run
+// away with the proxy, turned off
+fn engine(started: Instant) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_module_resolver(DummyModuleResolver::new())
+        .disable_symbol("eval")
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(4096)
+        .set_max_array_size(1024)
+        .set_max_map_size(256)
+        .on_progress(
+            move |_| match started.elapsed().as_millis() > TIME_LIMIT_MS {
+                true => Some(Dynamic::UNIT),
+                false => None,
+            },
+        );
+    engine
+}
+
+impl Scripts {
+    // The script at `path`, compiled again if it's changed since last time
+    fn load(&self, path: &str) -> Option<AST> {
+        let path = PathBuf::from(path);
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                println!("Failed to read script {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let (modified, len) = (metadata.modified().ok(), metadata.len());
+
+        let mut compiled = self.compiled.lock().unwrap();
+        if let Some(c) = compiled.get(&path) {
+            if c.modified == modified && c.len == len {
+                return c.ast.clone();
+            }
+        }
+        let ast = match fs::read_to_string(&path) {
+            Ok(source) => engine(Instant::now())
+                .compile(source)
+                .map_err(|e| println!("Failed to compile script {}: {}", path.display(), e))
+                .ok(),
+            Err(e) => {
+                println!("Failed to read script {}: {}", path.display(), e);
+                None
+            }
+        };
+        compiled.insert(
+            path,
+            Compiled {
+                modified,
+                len,
+                ast: ast.clone(),
+            },
+        );
+        ast
+    }
+
+    // Run the script at `path` for a sign that would otherwise show
+    // `rows`. Anything that goes wrong is logged, and leaves the sign alone.
+    pub fn run(
+        &self,
+        path: &str,
+        timeline: &Timeline,
+        rows: &[String],
+        now: DateTime<Utc>,
+        location: &LocationConfig,
+        geometry: Geometry,
+    ) -> Option<Outcome> {
+        let ast = self.load(path)?;
+
+        let mut scope = Scope::new();
+        scope.push_constant("events", events(timeline, now));
+        scope.push_constant("now", clock(now, location));
+        scope.push_constant(
+            "rows",
+            rows.iter().cloned().map(Dynamic::from).collect::<Array>(),
+        );
+        let mut display = Map::new();
+        display.insert("rows".into(), (geometry.rows as i64).into());
+        display.insert("cols".into(), (geometry.cols as i64).into());
+        scope.push_constant("display", display);
+
+        let result = match engine(Instant::now()).eval_ast_with_scope::<Dynamic>(&mut scope, &ast) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("Script {} failed: {}", path, e);
+                return None;
+            }
+        };
+        outcome(result).unwrap_or_else(|e| {
+            println!("Script {} returned {}", path, e);
+            None
+        })
+    }
+}
+
+// What came back, if it's something we understand
+fn outcome(result: Dynamic) -> Result<Option<Outcome>, String> {
+    if result.is_unit() {
+        return Ok(None);
+    }
+    if result.is_string() {
+        let name = result.into_string()?;
+        return serde_json::from_value(serde_json::Value::String(name.clone()))
+            .map(|layout| Some(Outcome::Layout(layout)))
+            .map_err(|_| format!("an unknown layout, {}", name));
+    }
+    if result.is_array() {
+        let rows = result
+            .into_array()?
+            .into_iter()
+            .map(|row| row.to_string())
+            .collect();
+        return Ok(Some(Outcome::Rows(rows)));
+    }
+    Err(format!("a {}", result.type_name()))
+}
+
+// The room's events, as maps. Times are Unix timestamps, and all-day events
+// have no start or end.
+fn events(timeline: &Timeline, now: DateTime<Utc>) -> Array {
+    timeline
+        .events
+        .iter()
+        .map(|e| {
+            let mut event = Map::new();
+            event.insert("title".into(), e.title().into());
+            let at = |t: Option<DateTime<Utc>>| t.map_or(Dynamic::UNIT, |t| t.timestamp().into());
+            event.insert("start".into(), at(e.start.date_time));
+            event.insert("end".into(), at(e.end.date_time));
+            event.insert("all_day".into(), e.start.date_time.is_none().into());
+            event.insert("happening".into(), e.is_happening(now).into());
+            event.insert("line".into(), e.sign().line.unwrap_or_default().into());
+            Dynamic::from(event)
+        })
+        .collect()
+}
+
+// The time, in the location's timezone, taken apart
+fn clock(now: DateTime<Utc>, location: &LocationConfig) -> Map {
+    let local = now.with_timezone(&location.timezone);
+    let mut clock = Map::new();
+    clock.insert("timestamp".into(), now.timestamp().into());
+    clock.insert("hour".into(), (local.hour() as i64).into());
+    clock.insert("minute".into(), (local.minute() as i64).into());
+    // Monday is 1
+    clock.insert(
+        "weekday".into(),
+        (local.weekday().number_from_monday() as i64).into(),
+    );
+    clock.insert("date".into(), local.format("%Y-%m-%d").to_string().into());
+    clock.insert("time".into(), location.clock.time(&local).into());
+    clock
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{event, Timeline};
+    use crate::config::LocationConfig;
+    use crate::render::{Geometry, Layout};
+    use crate::script::{Outcome, Scripts};
+    use chrono::prelude::*;
+    use chrono_tz::UTC;
+    use std::fs;
+
+    #[test]
+    fn test_run() {
+        let at = |h| Some(Utc.with_ymd_and_hms(2020, 1, 1, h, 0, 0).unwrap());
+        let timeline = Timeline {
+            events: vec![event("Smash", at(19).unwrap(), at(20).unwrap())],
+        };
+        let location = LocationConfig {
+            timezone: UTC,
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("sidegrade-{}.rhai", std::process::id()));
+        let path = path.to_str().unwrap();
+        let scripts = Scripts::default();
+        let run = |script: &str, h| {
+            fs::write(path, script).unwrap();
+            let rows = ["Smash".to_string(), "In 01:00:00".to_string()];
+            let now = at(h).unwrap();
+            scripts.run(path, &timeline, &rows, now, &location, Geometry::default())
+        };
+
+        let script = r#"
+            let on = events.filter(|e| e.happening);
+            if now.hour >= 23 { ["Quiet hours", "Goodnight!"] }
+            else if on.is_empty() { "agenda" }
+            else { [`${on[0].title} until ${on[0].end - now.timestamp}s`, rows[0]] }
+        "#;
+        assert_eq!(run(script, 18), Some(Outcome::Layout(Layout::Agenda)));
+        assert_eq!(
+            run(script, 19),
+            Some(Outcome::Rows(vec![
+                "Smash until 3600s".to_string(),
+                "Smash".to_string()
+            ]))
+        );
+        assert_eq!(
+            run(script, 23),
+            Some(Outcome::Rows(vec![
+                "Quiet hours".to_string(),
+                "Goodnight!".to_string()
+            ]))
+        );
+
+        // Picked up again when it changes, and cut off when it runs away
+        assert_eq!(run("()", 18), None);
+        assert_eq!(run("loop { }", 18), None);
+        assert_eq!(run("import \"secrets\" as s; 1", 18), None);
+        assert_eq!(run("\"upside_down\"", 18), None);
+
+        fs::remove_file(path).unwrap();
+    }
+}
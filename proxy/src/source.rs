@@ -0,0 +1,404 @@
+// Things for signs to show that don't come from the calendar, like house
+// news from a feed or a file of messages someone keeps up to date. Every
+// source is fetched on its own schedule and cached on its own, and one that
+// breaks keeps showing whatever it had last, so it never takes the rest of
+// a sign down with it.
+
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, sync::Mutex, thread, time};
+
+use crate::config::LocationConfig;
+use crate::plugin::{self, Plugin};
+use crate::render::{Geometry, Screen};
+
+// How long a feed gets to answer before we give up on it for now
+const FETCH_TIMEOUT_SECONDS: u64 = 10;
+
+// How often to look for sources that are due
+pub const CHECK_SECONDS: u64 = 1;
+
+// One thing from a source, like a headline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub text: String,
+    // Whether the sign should flash when it comes up
+    #[serde(default)]
+    pub flash: bool,
+}
+
+pub trait Source: Send + Sync {
+    // Everything the source has right now, newest first. Can take a while,
+    // so it's only called off the async threads.
+    fn fetch(&self) -> anyhow::Result<Vec<Item>>;
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceKind {
+    // An RSS or Atom feed, at a URL or in a file
+    Feed {
+        url: String,
+    },
+    // A text file with one item per line, picked up again when it changes
+    File {
+        path: String,
+    },
+    // A WebAssembly plugin, given its config and only allowed to fetch
+    // from the hosts listed
+    Plugin {
+        path: String,
+        #[serde(default)]
+        config: serde_json::Value,
+        #[serde(default)]
+        allow: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SourceConfig {
+    #[serde(flatten)]
+    pub kind: SourceKind,
+    // How long to hang on to what was fetched. Five minutes for feeds, and
+    // a few seconds for files, since they're cheap to look at.
+    pub refresh_seconds: Option<i64>,
+    // Only the first this many items
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+impl SourceConfig {
+    fn refresh(&self) -> Duration {
+        let seconds = self.refresh_seconds.unwrap_or(match self.kind {
+            SourceKind::Feed { .. } | SourceKind::Plugin { .. } => 300,
+            SourceKind::File { .. } => 5,
+        });
+        Duration::seconds(seconds.max(1))
+    }
+
+    fn source(&self) -> Box<dyn Source> {
+        match &self.kind {
+            SourceKind::Feed { url } => Box::new(Feed {
+                url: url.clone(),
+                limit: self.limit,
+            }),
+            SourceKind::File { path } => Box::new(TextFile {
+                path: path.clone(),
+                limit: self.limit,
+            }),
+            SourceKind::Plugin {
+                path,
+                config,
+                allow,
+            } => Box::new(Plugin {
+                path: path.clone(),
+                config: config.clone(),
+                allow: allow.clone(),
+                limit: self.limit,
+                fetcher: plugin::http_get,
+            }),
+        }
+    }
+}
+
+pub struct Feed {
+    url: String,
+    limit: usize,
+}
+
+impl Source for Feed {
+    fn fetch(&self) -> anyhow::Result<Vec<Item>> {
+        let xml = match self.url.starts_with("http://") || self.url.starts_with("https://") {
+            true => reqwest::blocking::Client::builder()
+                .timeout(time::Duration::from_secs(FETCH_TIMEOUT_SECONDS))
+                .build()?
+                .get(&self.url)
+                .send()?
+                .error_for_status()?
+                .text()?,
+            false => fs::read_to_string(&self.url)?,
+        };
+        let mut items = parse_feed(&xml)?;
+        items.truncate(self.limit);
+        Ok(items)
+    }
+}
+
+// The titles of the items in an RSS feed, or the entries in an Atom one
+pub fn parse_feed(xml: &str) -> anyhow::Result<Vec<Item>> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let root = doc.root_element().tag_name().name();
+    if !["rss", "RDF", "feed"].contains(&root) {
+        return Err(anyhow!("Not an RSS or Atom feed: <{}>", root));
+    }
+    let items = doc
+        .descendants()
+        .filter(|n| matches!(n.tag_name().name(), "item" | "entry"))
+        .filter_map(|n| {
+            n.children()
+                .find(|c| c.tag_name().name() == "title")
+                .and_then(|t| t.text())
+        })
+        .map(|title| Item {
+            text: title.split_whitespace().collect::<Vec<&str>>().join(" "),
+            flash: false,
+        })
+        .filter(|item| !item.text.is_empty())
+        .collect();
+    Ok(items)
+}
+
+pub struct TextFile {
+    path: String,
+    limit: usize,
+}
+
+impl Source for TextFile {
+    fn fetch(&self) -> anyhow::Result<Vec<Item>> {
+        let text = fs::read_to_string(&self.path)?;
+        Ok(text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .take(self.limit)
+            .map(|line| Item {
+                text: line.to_string(),
+                flash: false,
+            })
+            .collect())
+    }
+}
+
+struct Cached {
+    items: Vec<Item>,
+    fetched_at: Option<DateTime<Utc>>,
+}
+
+struct Entry {
+    source: Box<dyn Source>,
+    refresh: Duration,
+    cached: Mutex<Cached>,
+}
+
+// Every source in the config, and what each had last time it was asked
+pub struct Sources {
+    entries: HashMap<String, Entry>,
+}
+
+impl Sources {
+    pub fn new(configs: &HashMap<String, SourceConfig>) -> Self {
+        let entries = configs
+            .iter()
+            .map(|(name, config)| (name.clone(), (config.source(), config.refresh())))
+            .collect();
+        Self::from_sources(entries)
+    }
+
+    pub fn from_sources(sources: HashMap<String, (Box<dyn Source>, Duration)>) -> Self {
+        let entries = sources
+            .into_iter()
+            .map(|(name, (source, refresh))| {
+                let cached = Mutex::new(Cached {
+                    items: vec![],
+                    fetched_at: None,
+                });
+                (
+                    name,
+                    Entry {
+                        source,
+                        refresh,
+                        cached,
+                    },
+                )
+            })
+            .collect();
+        Sources { entries }
+    }
+
+    // Fetch the sources that are due, each on its own thread, so one slow
+    // feed doesn't hold up the rest. A source that fails keeps what it had,
+    // and isn't tried again until it's due again. Blocks until they're all
+    // back, so keep it off the async threads.
+    pub fn refresh(&self, now: DateTime<Utc>) {
+        thread::scope(|scope| {
+            for (name, entry) in &self.entries {
+                let due = entry
+                    .cached
+                    .lock()
+                    .unwrap()
+                    .fetched_at
+                    .is_none_or(|at| now - at >= entry.refresh);
+                if !due {
+                    continue;
+                }
+                scope.spawn(move || {
+                    // Without holding on to the cache, so a slow feed
+                    // doesn't keep the signs waiting
+                    let fetched = entry.source.fetch();
+                    let mut cached = entry.cached.lock().unwrap();
+                    match fetched {
+                        Ok(items) => cached.items = items,
+                        Err(e) => println!("Failed to fetch {}: {}", name, e),
+                    }
+                    cached.fetched_at = Some(now);
+                });
+            }
+        });
+    }
+
+    // What every source had when it was last fetched
+    pub fn items(&self) -> HashMap<String, Vec<Item>> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.cached.lock().unwrap().items.clone()))
+            .collect()
+    }
+}
+
+// Put a source's items on the last row, one at a time, changing every
+// page_seconds. The display scrolls the ones that are too long for it, and
+// flashes for the ones that ask.
+pub fn ticker(
+    screen: &mut Screen,
+    items: &[Item],
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    geometry: Geometry,
+) {
+    if items.is_empty() {
+        return;
+    }
+    let page_seconds = location.page_seconds.max(1);
+    let tick = now.timestamp().div_euclid(page_seconds);
+    let item = &items[tick.rem_euclid(items.len() as i64) as usize];
+    screen
+        .rows
+        .resize(geometry.content_rows() - 1, String::new());
+    screen.rows.push(item.text.clone());
+    screen.flash |= item.flash;
+    if items.len() > 1 {
+        let next = DateTime::<Utc>::from_timestamp((tick + 1) * page_seconds, 0).unwrap();
+        screen.refresh_at(next, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::{parse_feed, Item, Source, Sources};
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::{thread, time};
+
+    #[test]
+    fn test_parse_feed() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <title>House News</title>
+                <item><title>Pizza in the
+                    Lounge</title></item>
+                <item><title>Wiki: Printer &amp; scanner</title></item>
+            </channel></rss>"#;
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Wiki changes</title>
+                <entry><title>Edited: Main Page</title></entry>
+            </feed>"#;
+        let texts = |xml| -> Vec<String> {
+            parse_feed(xml)
+                .unwrap()
+                .into_iter()
+                .map(|i| i.text)
+                .collect()
+        };
+
+        assert_eq!(
+            texts(rss),
+            ["Pizza in the Lounge", "Wiki: Printer & scanner"]
+        );
+        assert_eq!(texts(atom), ["Edited: Main Page"]);
+        assert!(parse_feed("<html></html>").is_err());
+    }
+
+    // Works once, then breaks
+    struct Flaky(AtomicUsize);
+
+    impl Source for Flaky {
+        fn fetch(&self) -> anyhow::Result<Vec<Item>> {
+            match self.0.fetch_add(1, Ordering::Relaxed) {
+                0 => Ok(vec![Item {
+                    text: "Hello".to_string(),
+                    flash: false,
+                }]),
+                _ => Err(anyhow::anyhow!("Feed's down")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sources_cache() {
+        let mut sources: HashMap<String, (Box<dyn Source>, Duration)> = HashMap::new();
+        sources.insert(
+            "news".to_string(),
+            (Box::new(Flaky(AtomicUsize::new(0))), Duration::minutes(5)),
+        );
+        let sources = Sources::from_sources(sources);
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 0, 0).unwrap();
+        let hello = vec![Item {
+            text: "Hello".to_string(),
+            flash: false,
+        }];
+
+        // Nothing until it's been fetched
+        assert!(sources.items()["news"].is_empty());
+        sources.refresh(now);
+        assert_eq!(sources.items()["news"], hello);
+        // Cached until it's due, then broken, and still showing what it had
+        sources.refresh(now + Duration::minutes(1));
+        assert_eq!(sources.items()["news"], hello);
+        sources.refresh(now + Duration::minutes(6));
+        assert_eq!(sources.items()["news"], hello);
+    }
+
+    // Only works if the others are being fetched at the same time
+    struct Together(Arc<AtomicUsize>, usize);
+
+    impl Source for Together {
+        fn fetch(&self) -> anyhow::Result<Vec<Item>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let started = time::Instant::now();
+            while self.0.load(Ordering::SeqCst) < self.1 {
+                if started.elapsed() > time::Duration::from_secs(5) {
+                    anyhow::bail!("Fetched on its own");
+                }
+                thread::sleep(time::Duration::from_millis(10));
+            }
+            Ok(vec![Item {
+                text: "Together".to_string(),
+                flash: false,
+            }])
+        }
+    }
+
+    #[test]
+    fn test_sources_in_parallel() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let names = ["news", "wiki", "printer"];
+        let mut sources: HashMap<String, (Box<dyn Source>, Duration)> = HashMap::new();
+        for name in names {
+            let source = Together(started.clone(), names.len());
+            sources.insert(name.to_string(), (Box::new(source), Duration::minutes(5)));
+        }
+        let sources = Sources::from_sources(sources);
+        sources.refresh(Utc.with_ymd_and_hms(2020, 1, 1, 18, 0, 0).unwrap());
+        for name in names {
+            assert_eq!(sources.items()[name][0].text, "Together");
+        }
+    }
+}
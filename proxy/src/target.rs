@@ -0,0 +1,261 @@
+// Countdowns to things that aren't on the calendar, like "Imagine RIT in 23
+// days" or "Finals end in 4d 06h". They're set through the admin API, kept
+// in a file next to the location config, and go away on their own once the
+// moment they're counting down to has passed.
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf, sync::RwLock};
+
+use crate::calendar::Timeline;
+use crate::config::{Config, LocationConfig};
+use crate::countdown;
+use crate::power;
+use crate::render::{self, Geometry, Screen};
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Target {
+    pub id: u32,
+    pub title: String,
+    pub at: DateTime<Utc>,
+    // One or the other, or neither for every sign
+    pub location: Option<String>,
+    pub group: Option<String>,
+}
+
+impl Target {
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        now < self.at
+    }
+
+    // Whether a sign at this location should show it. Countdowns for a
+    // group go to the signs in each of its rooms.
+    pub fn reaches(&self, location: &str, config: &Config) -> bool {
+        match (&self.location, &self.group) {
+            (Some(l), _) => l.eq_ignore_ascii_case(location),
+            (None, Some(g)) => config
+                .group(g)
+                .is_some_and(|g| g.locations.iter().any(|m| m.eq_ignore_ascii_case(location))),
+            (None, None) => true,
+        }
+    }
+
+    // Imagine RIT
+    // In 23 days
+    fn rows(&self, now: DateTime<Utc>, location: &LocationConfig) -> [String; 2] {
+        [
+            self.title.clone(),
+            countdown::starts_in(self.at - now, self.at, location),
+        ]
+    }
+}
+
+// What gets posted to the API
+#[derive(Debug, Deserialize)]
+pub struct TargetRequest {
+    pub title: String,
+    pub at: DateTime<Utc>,
+    pub location: Option<String>,
+    pub group: Option<String>,
+}
+
+impl TargetRequest {
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if self.title.trim().is_empty() {
+            return Err("A countdown needs a title".to_string());
+        }
+        if self.at <= now {
+            return Err("A countdown has to count down to the future".to_string());
+        }
+        if self.location.is_some() && self.group.is_some() {
+            return Err("Pick a location or a group, not both".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Every live countdown for a sign, one to a page where there's room for
+// two rows, and on one row where there isn't
+pub fn screen(
+    targets: &[&Target],
+    timeline: &Timeline,
+    now: DateTime<Utc>,
+    location: &LocationConfig,
+    geometry: Geometry,
+) -> Screen {
+    let mut live: Vec<&Target> = targets.iter().filter(|t| t.is_live(now)).cloned().collect();
+    live.sort_by_key(|t| t.at);
+
+    let per_page = geometry.content_rows() - geometry.content_rows() % 2;
+    let rows = match per_page {
+        0 => live
+            .iter()
+            .map(|t| t.rows(now, location).join(" "))
+            .collect(),
+        _ => live.iter().flat_map(|t| t.rows(now, location)).collect(),
+    };
+    let mut screen = render::paged(rows, per_page.max(1), now, location.page_seconds);
+    // Check back when the next one's up
+    if let Some(first) = live.first() {
+        screen.refresh_at(first.at, now);
+    }
+    screen.power = power::power(&[timeline], now, location);
+    screen
+}
+
+// Where countdowns are kept: COUNTDOWN_CONFIG, or countdowns.json in the
+// same directory as the location config
+#[derive(Default)]
+pub struct Targets {
+    path: PathBuf,
+    targets: RwLock<Vec<Target>>,
+}
+
+impl Targets {
+    pub fn load() -> anyhow::Result<Self> {
+        let path = match env::var("COUNTDOWN_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let config = env::var("LOCATION_CONFIG").unwrap_or("locations.json".to_string());
+                PathBuf::from(config).with_file_name("countdowns.json")
+            }
+        };
+        let targets = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s).map_err(|e| anyhow!("{}: {}", path.display(), e))?,
+            Err(_) => vec![],
+        };
+        Ok(Targets {
+            path,
+            targets: RwLock::new(targets),
+        })
+    }
+
+    // Everything that hasn't passed yet
+    pub fn current(&self, now: DateTime<Utc>) -> Vec<Target> {
+        let targets = self.targets.read().unwrap();
+        targets.iter().filter(|t| t.is_live(now)).cloned().collect()
+    }
+
+    pub fn create(&self, request: TargetRequest, now: DateTime<Utc>) -> anyhow::Result<Target> {
+        let mut targets = self.targets.write().unwrap();
+        let target = Target {
+            id: targets.iter().map(|t| t.id).max().unwrap_or(0) + 1,
+            title: request.title,
+            at: request.at,
+            location: request.location,
+            group: request.group,
+        };
+        let mut updated = targets.clone();
+        updated.push(target.clone());
+        *targets = self.save(updated, now)?;
+        Ok(target)
+    }
+
+    // Whether there was anything to delete
+    pub fn delete(&self, id: u32, now: DateTime<Utc>) -> anyhow::Result<bool> {
+        let mut targets = self.targets.write().unwrap();
+        let mut updated = targets.clone();
+        updated.retain(|t| t.id != id);
+        let deleted = updated.len() < targets.len();
+        *targets = self.save(updated, now)?;
+        Ok(deleted)
+    }
+
+    // Write the file back out, dropping anything that's passed while we're
+    // at it. Hands back what was written, to take the place of what was
+    // there, so nothing changes unless it's been saved.
+    fn save(&self, mut targets: Vec<Target>, now: DateTime<Utc>) -> anyhow::Result<Vec<Target>> {
+        targets.retain(|t| t.is_live(now));
+        fs::write(&self.path, serde_json::to_string_pretty(&targets)?)
+            .map_err(|e| anyhow!("Could not save {}: {}", self.path.display(), e))?;
+        Ok(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::Timeline;
+    use crate::config::{Config, LocationConfig};
+    use crate::countdown::{CountdownConfig, DurationStyle};
+    use crate::render::Geometry;
+    use crate::target::{screen, Target, TargetRequest, Targets};
+    use chrono::prelude::*;
+    use chrono::Duration;
+    use chrono_tz::UTC;
+    use std::path::PathBuf;
+
+    fn target(title: &str, at: DateTime<Utc>) -> Target {
+        Target {
+            id: 1,
+            title: title.to_string(),
+            at,
+            location: None,
+            group: None,
+        }
+    }
+
+    #[test]
+    fn test_screen() {
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 0, 0).unwrap();
+        let location = LocationConfig {
+            timezone: UTC,
+            countdown: CountdownConfig {
+                style: DurationStyle::Compact,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rit = target("Imagine RIT", now + Duration::days(23));
+        let finals = target("Finals end", now + Duration::hours(102));
+        let over = target("Spring Break", now - Duration::days(1));
+        let timeline = Timeline { events: vec![] };
+        let all = [&rit, &finals, &over];
+
+        // Soonest first, one to a page, formatted like an event's countdown
+        let shown = screen(&all, &timeline, now, &location, Geometry::default());
+        assert_eq!(shown.rows, ["Finals end", "In 4d 6h"]);
+        assert_eq!(shown.page.as_deref(), Some("1/2"));
+        let one_row = Geometry::new(1, 40).unwrap();
+        let shown = screen(&all, &timeline, now, &location, one_row);
+        assert_eq!(shown.rows, ["Finals end In 4d 6h"]);
+
+        // Gone once it's passed
+        let later = now + Duration::days(5);
+        let shown = screen(&all, &timeline, later, &location, Geometry::default());
+        assert_eq!(shown.rows, ["Imagine RIT", "In 18d 0h"]);
+        assert_eq!(shown.page, None);
+    }
+
+    #[test]
+    fn test_reaches() {
+        let config: Config =
+            serde_json::from_str(include_str!("../locations.json.sample")).unwrap();
+        let mut t = target("Imagine RIT", Utc::now());
+        assert!(t.reaches("Attic", &config));
+        t.group = Some("hallway".to_string());
+        assert!(t.reaches("Library", &config));
+        assert!(!t.reaches("Attic", &config));
+    }
+
+    #[test]
+    fn test_unsaved() {
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 0, 0).unwrap();
+        let targets = Targets {
+            path: PathBuf::from("/nonexistent/countdowns.json"),
+            targets: vec![target("Move-out", now + Duration::days(3))].into(),
+        };
+        let request = TargetRequest {
+            title: "Graduation".to_string(),
+            at: now + Duration::days(30),
+            location: None,
+            group: None,
+        };
+
+        // Nothing changes if it can't be written down
+        assert!(targets.create(request, now).is_err());
+        assert!(targets.delete(1, now).is_err());
+        let titles: Vec<String> = targets.current(now).into_iter().map(|t| t.title).collect();
+        assert_eq!(titles, ["Move-out"]);
+    }
+}
@@ -0,0 +1,197 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+use crate::calendar::Timeline;
+use crate::config::LocationConfig;
+
+// Where a location is relative to its events
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Urgency {
+    #[default]
+    None,
+    // The next event starts within a few minutes
+    StartingSoon,
+    // The current event started less than a minute ago
+    StartingNow,
+    // The current event is in its last few minutes
+    EndingSoon,
+    // An event finished a moment ago, and nothing's on now
+    JustEnded,
+}
+
+impl Urgency {
+    // What goes in the header
+    pub fn name(&self) -> &'static str {
+        match self {
+            Urgency::None => "none",
+            Urgency::StartingSoon => "starting_soon",
+            Urgency::StartingNow => "starting_now",
+            Urgency::EndingSoon => "ending_soon",
+            Urgency::JustEnded => "just_ended",
+        }
+    }
+}
+
+// How a sign shows one of the states
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(default)]
+pub struct Presentation {
+    // Blink the display
+    pub flash: bool,
+    // Say so in words ("STARTING NOW") in place of the page number, or on
+    // the last row of panels with no room for a footer
+    pub label: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct UrgencyConfig {
+    pub starting_soon_minutes: i64,
+    pub ending_soon_minutes: i64,
+    pub just_ended_minutes: i64,
+    pub starting_soon: Presentation,
+    pub starting_now: Presentation,
+    pub ending_soon: Presentation,
+    pub just_ended: Presentation,
+}
+
+impl Default for UrgencyConfig {
+    fn default() -> Self {
+        UrgencyConfig {
+            starting_soon_minutes: 5,
+            ending_soon_minutes: 5,
+            just_ended_minutes: 2,
+            starting_soon: Presentation::default(),
+            starting_now: Presentation {
+                flash: true,
+                label: true,
+            },
+            ending_soon: Presentation {
+                flash: false,
+                label: true,
+            },
+            just_ended: Presentation {
+                flash: false,
+                label: true,
+            },
+        }
+    }
+}
+
+impl UrgencyConfig {
+    pub fn presentation(&self, urgency: Urgency) -> Presentation {
+        match urgency {
+            Urgency::None => Presentation::default(),
+            Urgency::StartingSoon => self.starting_soon,
+            Urgency::StartingNow => self.starting_now,
+            Urgency::EndingSoon => self.ending_soon,
+            Urgency::JustEnded => self.just_ended,
+        }
+    }
+}
+
+// Work out what state a location is in, and when that's next due to
+// change. What's going on now wins over what's coming up, which wins over
+// what's just finished.
+pub fn classify(
+    timeline: &Timeline,
+    now: DateTime<Utc>,
+    config: &UrgencyConfig,
+) -> (Urgency, Option<DateTime<Utc>>) {
+    let minutes = |m: i64| Duration::try_minutes(m).unwrap_or_default();
+
+    if let Some(e) = timeline.current(now) {
+        let (start, end) = (e.start.date_time.unwrap(), e.end.date_time.unwrap());
+        let first_minute = start + minutes(1);
+        if now < first_minute {
+            return (Urgency::StartingNow, Some(first_minute));
+        }
+        let ending_soon = end - minutes(config.ending_soon_minutes);
+        return match now >= ending_soon {
+            true => (Urgency::EndingSoon, Some(end)),
+            false => (Urgency::None, Some(ending_soon)),
+        };
+    }
+
+    let next_start = timeline.next(now).and_then(|e| e.start.date_time);
+    if let Some(start) = next_start {
+        if start - now <= minutes(config.starting_soon_minutes) {
+            return (Urgency::StartingSoon, Some(start));
+        }
+    }
+
+    let last_end = timeline
+        .events
+        .iter()
+        .filter_map(|e| e.end.date_time)
+        .filter(|&end| end <= now)
+        .max();
+    if let Some(end) = last_end {
+        let over = end + minutes(config.just_ended_minutes);
+        if now < over {
+            return (Urgency::JustEnded, Some(over));
+        }
+    }
+
+    let starting_soon = next_start.map(|s| s - minutes(config.starting_soon_minutes));
+    (Urgency::None, starting_soon)
+}
+
+// The words for a state, if it has any
+pub fn label(urgency: Urgency, location: &LocationConfig) -> Option<&'static str> {
+    let catalog = location.locale.catalog();
+    match urgency {
+        Urgency::StartingNow => Some(catalog.starting_now),
+        Urgency::EndingSoon => Some(catalog.ending_soon),
+        Urgency::JustEnded => Some(catalog.just_ended),
+        Urgency::None | Urgency::StartingSoon => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{at, event, Timeline};
+    use crate::urgency::{classify, Urgency, UrgencyConfig};
+    use chrono::prelude::*;
+
+    #[test]
+    fn test_classify() {
+        let timeline = Timeline {
+            events: vec![
+                event("Smash", at(19, 0), at(20, 0)),
+                event("Movie", at(21, 0), at(22, 0)),
+            ],
+        };
+        let config = UrgencyConfig::default();
+        let time = |h, m, s| Utc.with_ymd_and_hms(2020, 1, 1, h, m, s).unwrap();
+        let state = |h, m, s| classify(&timeline, time(h, m, s), &config);
+
+        assert_eq!(state(18, 50, 0), (Urgency::None, Some(time(18, 55, 0))));
+        assert_eq!(
+            state(18, 55, 0),
+            (Urgency::StartingSoon, Some(time(19, 0, 0)))
+        );
+        assert_eq!(
+            state(19, 0, 30),
+            (Urgency::StartingNow, Some(time(19, 1, 0)))
+        );
+        assert_eq!(state(19, 1, 0), (Urgency::None, Some(time(19, 55, 0))));
+        assert_eq!(
+            state(19, 57, 0),
+            (Urgency::EndingSoon, Some(time(20, 0, 0)))
+        );
+        assert_eq!(state(20, 1, 0), (Urgency::JustEnded, Some(time(20, 2, 0))));
+        assert_eq!(state(20, 2, 0), (Urgency::None, Some(time(20, 55, 0))));
+        // Something starting soon beats something that just ended
+        let timeline = Timeline {
+            events: vec![
+                event("Smash", at(19, 0), at(20, 0)),
+                event("Movie", at(20, 3), at(22, 0)),
+            ],
+        };
+        assert_eq!(
+            classify(&timeline, time(20, 0, 30), &config).0,
+            Urgency::StartingSoon
+        );
+    }
+}
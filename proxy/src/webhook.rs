@@ -0,0 +1,407 @@
+// Webhooks, for things like the Discord bot and the house automation that
+// want to know when a room's state changes without polling a sign's route
+// and reading its text. A location can have any number of them, each with
+// a secret of its own that every POST is signed with:
+//
+// X-Sidegrade-Signature: sha256=<HMAC-SHA256 of the body, in hex>
+//
+// Deliveries that still fail after a few tries get written to a
+// dead-letter log, so nothing gets lost without a trace.
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    sync::Arc,
+    time,
+};
+
+use crate::calendar::{Event, Timeline};
+use crate::clock::Clock;
+use crate::config::LocationConfig;
+
+pub const SIGNATURE_HEADER: &str = "X-Sidegrade-Signature";
+// How often rooms get looked at for anything to send
+pub const CHECK_SECONDS: u64 = 15;
+// How long to wait before each retry of a delivery that failed
+const BACKOFF_SECONDS: [u64; 3] = [5, 30, 120];
+const DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+// How far ahead changes to the schedule are worth sending. Past this, the
+// calendar's list gets cut off, and events slide in and out of it as others
+// end.
+const SCHEDULE_HOURS: i64 = 24;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Transition {
+    // Free to busy
+    Busy,
+    // Busy to free
+    Free,
+    // An event is about to start, as early as the urgency config's
+    // starting_soon_minutes
+    StartingSoon,
+    // Something on the room's schedule was added, moved or taken off
+    ScheduleChanged,
+}
+
+fn every_transition() -> Vec<Transition> {
+    vec![
+        Transition::Busy,
+        Transition::Free,
+        Transition::StartingSoon,
+        Transition::ScheduleChanged,
+    ]
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Shared with whoever's listening, so they can tell it's us
+    pub secret: String,
+    // What to send. Everything, when it's left out.
+    #[serde(default = "every_transition")]
+    pub on: Vec<Transition>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct EventInfo {
+    pub id: Option<String>,
+    pub title: String,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl From<&Event> for EventInfo {
+    fn from(event: &Event) -> Self {
+        EventInfo {
+            id: event.id.clone(),
+            title: event.title(),
+            start: event.start.date_time,
+            end: event.end.date_time,
+        }
+    }
+}
+
+// What gets POSTed
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Payload {
+    pub transition: Transition,
+    pub location: String,
+    pub at: DateTime<Utc>,
+    // The event that's starting, or just started
+    pub event: Option<EventInfo>,
+    // Everything that's still to come, when the schedule changes
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<EventInfo>,
+}
+
+// What makes a booking the same booking from one look to the next. Titles
+// can be tidied up without it counting as a change to the schedule.
+#[derive(Debug, Clone, PartialEq)]
+struct Booking {
+    id: Option<String>,
+    start: DateTime<Utc>,
+    end: Option<DateTime<Utc>>,
+    location: Option<String>,
+}
+
+// A room as it was the last time it was looked at
+struct Seen {
+    at: DateTime<Utc>,
+    busy: bool,
+    // Events we've already said are starting soon
+    announced: HashSet<(Option<String>, Option<DateTime<Utc>>)>,
+    bookings: Vec<Booking>,
+}
+
+// Keeps track of every room with webhooks, to tell what's changed
+#[derive(Default)]
+pub struct Watcher {
+    seen: HashMap<String, Seen>,
+}
+
+// Every booking with a time to it. All-day events don't take a room.
+fn bookings(events: &[Event]) -> Vec<Booking> {
+    events
+        .iter()
+        .filter_map(|e| {
+            Some(Booking {
+                id: e.id.clone(),
+                start: e.start.date_time?,
+                end: e.end.date_time,
+                location: e.location.clone(),
+            })
+        })
+        .collect()
+}
+
+// The ones that aren't over by `now` and start before `until`
+fn within(bookings: &[Booking], now: DateTime<Utc>, until: DateTime<Utc>) -> Vec<Booking> {
+    bookings
+        .iter()
+        .filter(|b| b.end.is_none_or(|end| end > now) && b.start < until)
+        .cloned()
+        .collect()
+}
+
+impl Watcher {
+    // What's happened at a location since the last look. The first look
+    // only takes note of how things are, so a restart doesn't send
+    // everything all over again.
+    pub fn observe(
+        &mut self,
+        location: &str,
+        timeline: &Timeline,
+        now: DateTime<Utc>,
+        config: &LocationConfig,
+    ) -> Vec<Payload> {
+        let current = timeline.current(now);
+        let busy = current.is_some();
+        let soon = now + Duration::minutes(config.urgency.starting_soon_minutes);
+        let starting: Vec<&Event> = timeline
+            .events
+            .iter()
+            .filter(|e| e.start.date_time.is_some_and(|s| s > now && s <= soon))
+            .collect();
+        let key = |e: &Event| (e.id.clone(), e.start.date_time);
+        let horizon = now + Duration::hours(SCHEDULE_HOURS);
+
+        let payload = |transition, event: Option<&Event>| Payload {
+            transition,
+            location: location.to_string(),
+            at: now,
+            event: event.map(EventInfo::from),
+            schedule: vec![],
+        };
+        let mut payloads = vec![];
+        if let Some(seen) = self.seen.get(location) {
+            match (seen.busy, busy) {
+                (false, true) => payloads.push(payload(Transition::Busy, current)),
+                (true, false) => payloads.push(payload(Transition::Free, None)),
+                _ => (),
+            }
+            for event in &starting {
+                if !seen.announced.contains(&key(event)) {
+                    payloads.push(payload(Transition::StartingSoon, Some(event)));
+                }
+            }
+            // Only as far ahead as both looks could see, so events coming
+            // into view as time passes aren't mistaken for new ones
+            let until = seen.at + Duration::hours(SCHEDULE_HOURS);
+            if within(&seen.bookings, now, until) != within(&bookings(&timeline.events), now, until)
+            {
+                let schedule = timeline
+                    .events
+                    .iter()
+                    .filter(|e| e.end.date_time.is_none_or(|end| end > now))
+                    .filter(|e| e.start.date_time.is_some_and(|s| s < horizon))
+                    .map(EventInfo::from)
+                    .collect();
+                payloads.push(Payload {
+                    schedule,
+                    ..payload(Transition::ScheduleChanged, None)
+                });
+            }
+        }
+
+        self.seen.insert(
+            location.to_string(),
+            Seen {
+                at: now,
+                busy,
+                announced: starting.iter().map(|e| key(e)).collect(),
+                bookings: within(&bookings(&timeline.events), now, horizon),
+            },
+        );
+        payloads
+    }
+}
+
+// sha256=<hex>, for the signature header
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+// Where deliveries that never made it get written down, one JSON object to
+// a line: WEBHOOK_DEAD_LETTERS, or dead-letters.jsonl in the same directory
+// as the location config
+#[derive(Clone)]
+pub struct DeadLetters {
+    path: PathBuf,
+}
+
+impl DeadLetters {
+    pub fn open() -> Self {
+        let path = match env::var("WEBHOOK_DEAD_LETTERS") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let config = env::var("LOCATION_CONFIG").unwrap_or("locations.json".to_string());
+                PathBuf::from(config).with_file_name("dead-letters.jsonl")
+            }
+        };
+        DeadLetters { path }
+    }
+
+    fn write(
+        &self,
+        url: &str,
+        payload: &Payload,
+        error: &str,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<()> {
+        let letter = serde_json::json!({
+            "url": url,
+            "payload": payload,
+            "error": error,
+            "failed_at": now,
+        });
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", letter)?;
+        Ok(())
+    }
+}
+
+async fn post(hook: &WebhookConfig, body: &[u8]) -> anyhow::Result<()> {
+    reqwest::Client::builder()
+        .timeout(time::Duration::from_secs(DELIVERY_TIMEOUT_SECONDS))
+        .build()?
+        .post(&hook.url)
+        .header("Content-Type", "application/json")
+        .header(SIGNATURE_HEADER, sign(&hook.secret, body))
+        .body(body.to_vec())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// Send a payload to a webhook, trying again a few times if it doesn't take,
+// and giving up to the dead-letter log if it never does
+pub async fn deliver(
+    hook: WebhookConfig,
+    payload: Payload,
+    dead_letters: DeadLetters,
+    clock: Arc<dyn Clock>,
+) {
+    let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(e) => return println!("Failed to serialize webhook payload: {}", e),
+    };
+    let mut waits = BACKOFF_SECONDS.iter();
+    loop {
+        let error = match post(&hook, &body).await {
+            Ok(()) => return,
+            Err(e) => e.to_string(),
+        };
+        match waits.next() {
+            Some(wait) => {
+                println!(
+                    "Webhook to {} failed, retrying in {}s: {}",
+                    hook.url, wait, error
+                );
+                actix_web::rt::time::sleep(time::Duration::from_secs(*wait)).await;
+            }
+            None => {
+                println!("Webhook to {} failed for good: {}", hook.url, error);
+                let now = clock.now();
+                let written = actix_web::rt::task::spawn_blocking(move || {
+                    dead_letters.write(&hook.url, &payload, &error, now)
+                })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|written| written);
+                if let Err(e) = written {
+                    println!("Failed to write dead letter: {}", e);
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{event, Event, Timeline};
+    use crate::config::LocationConfig;
+    use crate::webhook::{sign, Transition, Watcher};
+    use chrono::prelude::*;
+    use chrono::Duration;
+
+    fn booked(id: &str, start: DateTime<Utc>) -> Event {
+        event(id, start, start + Duration::hours(1)).with_id(id)
+    }
+
+    #[test]
+    fn test_observe() {
+        let now = Utc.with_ymd_and_hms(2020, 1, 1, 18, 0, 0).unwrap();
+        let location = LocationConfig::default();
+        let smash = booked("Smash", now + Duration::minutes(20));
+        let timeline = Timeline {
+            events: vec![smash.clone()],
+        };
+        let mut watcher = Watcher::default();
+        let mut observe = |timeline: &Timeline, minutes| -> Vec<Transition> {
+            let at = now + Duration::minutes(minutes);
+            watcher
+                .observe("Lounge", timeline, at, &location)
+                .iter()
+                .map(|p| p.transition)
+                .collect()
+        };
+
+        // Nothing the first time, or when nothing's changed
+        assert_eq!(observe(&timeline, 0), []);
+        assert_eq!(observe(&timeline, 1), []);
+        // Starting soon only once
+        assert_eq!(observe(&timeline, 16), [Transition::StartingSoon]);
+        assert_eq!(observe(&timeline, 17), []);
+        assert_eq!(observe(&timeline, 20), [Transition::Busy]);
+
+        let movie = booked("Movie", now + Duration::hours(3));
+        let changed = Timeline {
+            events: vec![smash, movie.clone()],
+        };
+        assert_eq!(observe(&changed, 30), [Transition::ScheduleChanged]);
+        // An event finishing isn't a change to the schedule
+        assert_eq!(observe(&changed, 80), [Transition::Free]);
+
+        // Nor is it when it drops off the calendar's list and lets one from
+        // further out in, or when a title gets tidied up
+        let later = Timeline {
+            events: vec![
+                Event {
+                    summary: "Movie Night".to_string(),
+                    ..movie
+                },
+                booked("Brunch", now + Duration::hours(26)),
+            ],
+        };
+        assert_eq!(observe(&later, 81), []);
+    }
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+}
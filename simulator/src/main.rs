@@ -0,0 +1,196 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use display_core::{
+    proxy::{self, Directives, Response},
+    scroll::ScrollConfig,
+    sign::{Power, Sign},
+};
+
+mod terminal;
+
+use crate::terminal::{StdClock, Terminal};
+
+// The same defaults as the firmware's config.rs.sample
+const HZ: u64 = 10000;
+const EMERGENCY_POLL_MS: u64 = 2000;
+const SCROLL_STEP_MS: u64 = 400;
+const SCROLL_DWELL_MS: u64 = 2000;
+
+// How often to look for new text when nothing is scrolling
+const IDLE_MS: u64 = 250;
+
+const USAGE: &str = "Usage: simulator [--rows N] [--cols N] [--emergency URL] <proxy route>
+e.g. simulator http://localhost:8080/locations/lounge/event";
+
+struct Options {
+    route: String,
+    // Where to check for emergency broadcasts. The same proxy's, unless
+    // told otherwise.
+    emergency: Option<String>,
+    rows: usize,
+    cols: usize,
+}
+
+// http://host:port/whatever -> http://host:port/emergency
+fn emergency_route(route: &str) -> Option<String> {
+    let (scheme, rest) = route.split_once("://")?;
+    let host = rest.split('/').next()?;
+    Some(format!("{}://{}/emergency", scheme, host))
+}
+
+fn options() -> anyhow::Result<Options> {
+    let mut route = env::var("PROXY_ROUTE").ok();
+    let mut rows = 4;
+    let mut cols = 20;
+    let mut emergency = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rows" | "--cols" => {
+                let value = args
+                    .next()
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .ok_or(anyhow!("{} needs a number", arg))?;
+                match arg.as_str() {
+                    "--rows" => rows = value,
+                    _ => cols = value,
+                }
+            }
+            "--emergency" => {
+                emergency = Some(args.next().ok_or(anyhow!("--emergency needs a URL"))?)
+            }
+            "-h" | "--help" => bail!(USAGE),
+            _ => route = Some(arg),
+        }
+    }
+
+    match route {
+        Some(route) => Ok(Options {
+            emergency: emergency.or(emergency_route(&route)),
+            route,
+            rows,
+            cols,
+        }),
+        None => bail!(USAGE),
+    }
+}
+
+// A route with the panel's size tacked on
+fn sized(route: &str, options: &Options) -> String {
+    let separator = if route.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}rows={}&cols={}",
+        route, separator, options.rows, options.cols
+    )
+}
+
+// Ask the proxy for a screen, the same way a sign does
+fn query_proxy(options: &Options) -> anyhow::Result<Response> {
+    let url = sized(&options.route, options);
+    let response = reqwest::blocking::Client::new()
+        .get(url)
+        .header("accept", "text/plain")
+        .send()?;
+    let headers = response.headers().clone();
+    let text = response.text()?;
+    Ok(Response::parse(&text, |name| {
+        headers.get(name).and_then(|h| h.to_str().ok())
+    }))
+}
+
+// The broadcast's rows, or None when there isn't one going
+fn query_emergency(route: &str, options: &Options) -> anyhow::Result<Option<Vec<String>>> {
+    let response = reqwest::blocking::Client::new()
+        .get(sized(route, options))
+        .header("accept", "text/plain")
+        .send()?;
+    let status = response.status().as_u16();
+    proxy::broadcast(status, &response.text()?).map_err(|s| anyhow!("Unexpected status {}", s))
+}
+
+// Wait out the delay until the next query, checking for emergencies on
+// the way like the firmware does
+fn watch_emergency(delay: u64, options: &Options, directives: &Mutex<Directives>) {
+    let route = match &options.emergency {
+        Some(route) => route,
+        None => return thread::sleep(Duration::from_millis(delay)),
+    };
+    proxy::watch_emergency(
+        &mut StdClock::new(),
+        delay,
+        EMERGENCY_POLL_MS,
+        || query_emergency(route, options),
+        |alert| std::mem::replace(&mut directives.lock().unwrap().emergency, alert),
+    );
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = options()?;
+
+    let directives = Arc::new(Mutex::new(Directives {
+        rows: vec![String::from("Query Proxy...")],
+        ..Default::default()
+    }));
+    let status = Arc::new(Mutex::new(String::new()));
+
+    let query_directives = Arc::clone(&directives);
+    let query_status = Arc::clone(&status);
+    let (rows, cols) = (options.rows, options.cols);
+    thread::spawn(move || loop {
+        let mut delay = HZ;
+        match query_proxy(&options) {
+            Ok(r) => {
+                delay = r.delay(HZ);
+                let urgency = r.urgency.map(|u| format!(" [{}]", u)).unwrap_or_default();
+                *query_status.lock().unwrap() = format!(
+                    "{}{} (next query in {}s)",
+                    options.route,
+                    urgency,
+                    delay / 1000
+                );
+                let mut directives = query_directives.lock().unwrap();
+                directives.rows = r.rows;
+                directives.flash = r.flash;
+                directives.power = r.power;
+            }
+            Err(e) => {
+                *query_status.lock().unwrap() = format!("Error: {}", e);
+                let mut directives = query_directives.lock().unwrap();
+                directives.rows = vec![
+                    "Could not fetch updates.".to_string(),
+                    e.to_string(),
+                    "Check Proxy?".to_string(),
+                ];
+                // Like the firmware, blink and stay lit so someone notices
+                directives.flash = true;
+                directives.power = Power::On;
+            }
+        }
+        watch_emergency(delay, &options, &query_directives);
+    });
+
+    let mut sign = Sign::new(
+        Terminal::new(rows, cols),
+        ScrollConfig {
+            step_ms: SCROLL_STEP_MS,
+            dwell_ms: SCROLL_DWELL_MS,
+        },
+    );
+    let mut shown = String::new();
+    sign.run(&mut StdClock::new(), IDLE_MS, |terminal| {
+        let status = status.lock().unwrap().clone();
+        if status != shown {
+            terminal.status(&status);
+            shown = status;
+        }
+        directives.lock().unwrap().clone()
+    })?;
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::{
+    convert::Infallible,
+    io::{stdout, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use display_core::{charset, CharDisplay, Clock};
+
+// White on blue, like the backlit 2004s in the house, and what's left
+// with the backlight off
+const LCD: &str = "\x1b[97;44m";
+const UNLIT: &str = "\x1b[90;40m";
+const RESET: &str = "\x1b[0m";
+
+// A sign, drawn in the terminal with ANSI escapes. The panel sits in a
+// frame in the top left corner, and the status line goes under it.
+pub struct Terminal {
+    rows: usize,
+    cols: usize,
+    // What's on each row, so it can come back after a blink
+    lines: Vec<String>,
+    visible: bool,
+    backlit: bool,
+}
+
+impl Terminal {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        let border = format!("+{}+", "-".repeat(cols + 2));
+        let mut out = String::from("\x1b[2J\x1b[H");
+        out.push_str(&border);
+        out.push('\n');
+        for _ in 0..rows {
+            out.push_str(&format!("|{} {} {}|\n", LCD, " ".repeat(cols), RESET));
+        }
+        out.push_str(&border);
+        out.push('\n');
+        print!("{}", out);
+        let _ = stdout().flush();
+        Terminal {
+            rows,
+            cols,
+            lines: vec![" ".repeat(cols); rows],
+            visible: true,
+            backlit: true,
+        }
+    }
+
+    fn draw(&self, row: usize) {
+        let line = match self.visible {
+            true => self.lines[row].clone(),
+            false => " ".repeat(self.cols),
+        };
+        let colour = match self.backlit {
+            true => LCD,
+            false => UNLIT,
+        };
+        // Row 1 is the top of the frame. The panel has a column of padding
+        // on either side, which changes colour with the rest of it.
+        print!("\x1b[{};2H{} {} {}", row + 2, colour, line, RESET);
+    }
+
+    fn redraw(&self) {
+        for row in 0..self.rows {
+            self.draw(row);
+        }
+        let _ = stdout().flush();
+    }
+
+    // A line of text under the panel, for what the simulator is up to
+    pub fn status(&self, text: &str) {
+        print!("\x1b[{};1H\x1b[2K{}", self.rows + 3, text);
+        let _ = stdout().flush();
+    }
+}
+
+impl CharDisplay for Terminal {
+    type Error = Infallible;
+
+    fn rows(&self) -> usize {
+        self.rows
+    }
+
+    fn cols(&self) -> usize {
+        self.cols
+    }
+
+    fn write_row(&mut self, row: usize, text: &[u8]) -> Result<(), Infallible> {
+        self.lines[row] = text.iter().map(|&b| charset::decode(b)).collect();
+        self.draw(row);
+        let _ = stdout().flush();
+        Ok(())
+    }
+
+    fn set_visible(&mut self, visible: bool) -> Result<(), Infallible> {
+        self.visible = visible;
+        self.redraw();
+        Ok(())
+    }
+
+    fn set_backlight(&mut self, on: bool) -> Result<(), Infallible> {
+        self.backlit = on;
+        self.redraw();
+        Ok(())
+    }
+}
+
+// The wall clock, measured from when the simulator started
+pub struct StdClock {
+    start: Instant,
+}
+
+impl StdClock {
+    pub fn new() -> Self {
+        StdClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for StdClock {
+    fn now_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn sleep_ms(&mut self, ms: u64) {
+        thread::sleep(Duration::from_millis(ms));
+    }
+}